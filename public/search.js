@@ -0,0 +1,90 @@
+// Client side search over the bloom filter index written by `src/search.rs`.
+const searchInput = document.querySelector('#search-input');
+const searchResults = document.querySelector('#search-results');
+let searchIndex = null;
+
+// Must stay in sync with `trigrams` in `src/search.rs`.
+function tokens(word) {
+  const chars = Array.from(word);
+  if (chars.length < 3) {
+    return [word];
+  }
+  const result = [];
+  for (let i = 0; i + 3 <= chars.length; i++) {
+    result.push(chars.slice(i, i + 3).join(''));
+  }
+  return result;
+}
+
+function fnv1a(bytes, seed) {
+  let hash = (0x811c9dc5 ^ seed) >>> 0;
+  for (const byte of bytes) {
+    hash ^= byte;
+    hash = Math.imul(hash, 0x01000193) >>> 0;
+  }
+  return hash;
+}
+
+const encoder = new TextEncoder();
+function contains(article, gram) {
+  const bytes = encoder.encode(gram);
+  const h1 = fnv1a(bytes, 0);
+  const h2 = fnv1a(bytes, 0x5bd1e995);
+  for (let i = 0; i < article.hashes; i++) {
+    const position = ((h1 + Math.imul(i, h2)) >>> 0) % article.bits;
+    if ((article.signature[position >> 3] & (1 << (position & 7))) === 0) {
+      return false;
+    }
+  }
+  return true;
+}
+
+function search(query) {
+  const words = query.toLowerCase().split(/[^\p{Alphabetic}\p{N}]+/u).filter(w => w.length > 0);
+  if (words.length === 0) {
+    return [];
+  }
+  const results = [];
+  for (const article of searchIndex.articles) {
+    // score by the amount of query words that (probably) occur in the article
+    let score = 0;
+    for (const word of words) {
+      if (tokens(word).every(gram => contains(article, gram))) {
+        score += 1;
+      }
+    }
+    if (score > 0) {
+      results.push({ article, score });
+    }
+  }
+  results.sort((a, b) => b.score - a.score);
+  return results.map(result => result.article);
+}
+
+function showResults() {
+  searchResults.replaceChildren();
+  for (const article of search(searchInput.value)) {
+    const item = document.createElement('li');
+    const link = document.createElement('a');
+    link.href = article.url;
+    link.textContent = article.title;
+    item.appendChild(link);
+    if (article.date) {
+      const time = document.createElement('time');
+      time.textContent = article.date;
+      item.appendChild(time);
+    }
+    searchResults.appendChild(item);
+  }
+}
+
+fetch('search_index.json')
+  .then(response => response.json())
+  .then(index => {
+    for (const article of index.articles) {
+      article.signature = Uint8Array.from(atob(article.signature), c => c.charCodeAt(0));
+    }
+    searchIndex = index;
+    searchInput.addEventListener('input', showResults);
+    showResults();
+  });
@@ -1,190 +1,182 @@
-use askama::Template;
-use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag, TagEnd};
+use latex2mathml::{DisplayStyle, latex_to_mathml};
+use pulldown_cmark::{BlockQuoteKind, CodeBlockKind, CowStr, Event, LinkType, Tag, TagEnd};
 use serde::Deserialize;
-use std::{io::Write as _, path::PathBuf};
-
-use crate::templates::article::ArticleTemplate;
-
-const HIGHLIGHT_NAMES: &'static [&'static str] = &[
-    "attribute",
-    "boolean",
-    "carriage-return",
-    "comment",
-    "comment.documentation",
-    "constant",
-    "constant.builtin",
-    "constructor",
-    "constructor.builtin",
-    "embedded",
-    "error",
-    "escape",
-    "function",
-    "function.builtin",
-    "keyword",
-    "markup",
-    "markup.bold",
-    "markup.heading",
-    "markup.italic",
-    "markup.link",
-    "markup.link.url",
-    "markup.list",
-    "markup.list.checked",
-    "markup.list.numbered",
-    "markup.list.unchecked",
-    "markup.list.unnumbered",
-    "markup.quote",
-    "markup.raw",
-    "markup.raw.block",
-    "markup.raw.inline",
-    "markup.strikethrough",
-    "module",
-    "number",
-    "operator",
-    "property",
-    "property.builtin",
-    "punctuation",
-    "punctuation.bracket",
-    "punctuation.delimiter",
-    "punctuation.special",
-    "string",
-    "string.escape",
-    "string.regexp",
-    "string.special",
-    "string.special.symbol",
-    "tag",
-    "type",
-    "type.builtin",
-    "variable",
-    "variable.builtin",
-    "variable.member",
-    "variable.parameter",
-];
-
-const CLASS_NAMES: &'static [&'static str] = &[
-    "attribute",
-    "boolean",
-    "carriage-return",
-    "comment",
-    "comment-documentation",
-    "constant",
-    "constant-builtin",
-    "constructor",
-    "constructor-builtin",
-    "embedded",
-    "error",
-    "escape",
-    "function",
-    "function-builtin",
-    "keyword",
-    "markup",
-    "markup-bold",
-    "markup-heading",
-    "markup-italic",
-    "markup-link",
-    "markup-link-url",
-    "markup-list",
-    "markup-list-checked",
-    "markup-list-numbered",
-    "markup-list-unchecked",
-    "markup-list-unnumbered",
-    "markup-quote",
-    "markup-raw",
-    "markup-raw-block",
-    "markup-raw-inline",
-    "markup-strikethrough",
-    "module",
-    "number",
-    "operator",
-    "property",
-    "property-builtin",
-    "punctuation",
-    "punctuation-bracket",
-    "punctuation-delimiter",
-    "punctuation-special",
-    "string",
-    "string-escape",
-    "string-regexp",
-    "string-special",
-    "string-special-symbol",
-    "tag",
-    "type",
-    "type-builtin",
-    "variable",
-    "variable-builtin",
-    "variable-member",
-    "variable-parameter",
-];
-
-fn render_code(lang: &str, code: &str) -> String {
-    let (language, highlights_query) = match lang {
-        "rust" => (
-            tree_sitter_rust::LANGUAGE.into(),
-            tree_sitter_rust::HIGHLIGHTS_QUERY,
-        ),
-        "python" => (
-            tree_sitter_python::LANGUAGE.into(),
-            tree_sitter_python::HIGHLIGHTS_QUERY,
-        ),
-        _ => panic!("unknown code fence {}", lang),
-    };
-    let mut highlighter_config = tree_sitter_highlight::HighlightConfiguration::new(
-        language,
-        lang,
-        highlights_query,
-        "",
-        "",
-    )
-    .unwrap();
-    highlighter_config.configure(HIGHLIGHT_NAMES);
-    let mut highlighter = tree_sitter_highlight::Highlighter::new();
-    let highlights = highlighter
-        .highlight(&highlighter_config, code.as_bytes(), None, |_| None)
-        .unwrap();
-
-    let mut html_highlighter = tree_sitter_highlight::HtmlRenderer::new();
-    html_highlighter
-        .render(highlights, code.as_bytes(), &|h, text| {
-            write!(text, r#"class="{}" "#, CLASS_NAMES[h.0]).unwrap();
-        })
-        .unwrap();
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    ops::Range,
+    path::{Path, PathBuf},
+};
 
-    let mut output = String::new();
-    output.push_str("<pre><code>");
-    for line in html_highlighter.lines() {
-        output.push_str(line);
+use crate::{
+    callouts::Callout,
+    db::Severity,
+    highlight,
+    html::escape_html,
+    wikilinks::{Resolved, WikiLinks},
+};
+
+/// A problem found while rendering, `range` is the byte range in the markdown.
+pub struct Problem {
+    pub severity: Severity,
+    pub range: Range<usize>,
+    pub message: String,
+    pub help: Option<String>,
+}
+
+/// Collects problems, locating them using the source ranges of the events.
+struct Problems<'r> {
+    ranges: &'r [Range<usize>],
+    list: Vec<Problem>,
+}
+
+impl<'r> Problems<'r> {
+    /// Range of the first event in `remaining`, which has to be a suffix of
+    /// all the events.
+    fn range_of(&self, remaining: &[Event]) -> Range<usize> {
+        self.ranges
+            .get(self.ranges.len() - remaining.len())
+            .cloned()
+            .unwrap_or(0..0)
+    }
+
+    fn push(
+        &mut self,
+        severity: Severity,
+        range: Range<usize>,
+        message: String,
+        help: Option<String>,
+    ) {
+        self.list.push(Problem {
+            severity,
+            range,
+            message,
+            help,
+        });
+    }
+
+    fn error(&mut self, range: Range<usize>, message: String) {
+        self.push(Severity::Error, range, message, None);
+    }
+
+    fn warning(&mut self, range: Range<usize>, message: String) {
+        self.push(Severity::Warning, range, message, None);
     }
-    output.push_str("</code></pre>");
-    output
 }
 
 fn handle_code_block<'a>(
     code_block_kind: &CodeBlockKind<'a>,
+    block_range: Range<usize>,
     mut events: &'a [Event<'a>],
+    problems: &mut Problems,
     mut emit: impl FnMut(Event<'a>),
 ) -> &'a [Event<'a>] {
+    let lang = match code_block_kind {
+        pulldown_cmark::CodeBlockKind::Indented => "".into(),
+        pulldown_cmark::CodeBlockKind::Fenced(cow_str) => cow_str.clone(),
+    };
     let mut code = String::new();
     while events.len() > 0 {
         let next_event = &events[0];
-        events = &events[1..];
         match next_event {
             Event::Text(text) => {
                 code.push_str(&text);
             }
             Event::End(TagEnd::CodeBlock) => {
-                let lang = match code_block_kind {
-                    pulldown_cmark::CodeBlockKind::Indented => "".into(),
-                    pulldown_cmark::CodeBlockKind::Fenced(cow_str) => cow_str.clone(),
+                let html = match highlight::find_language(&lang) {
+                    Some(language) => match highlight::render_code(language, &code) {
+                        Ok(html) => html,
+                        Err(err) => {
+                            problems.warning(
+                                block_range,
+                                format!("could not highlight {lang} code: {err}"),
+                            );
+                            highlight::render_plain(&code)
+                        }
+                    },
+                    None if lang.is_empty() => {
+                        problems.push(
+                            Severity::Warning,
+                            block_range,
+                            "code block without a language, rendering it without highlighting"
+                                .to_owned(),
+                            Some("add a language after the opening fence, like ```rust".to_owned()),
+                        );
+                        highlight::render_plain(&code)
+                    }
+                    None => {
+                        problems.push(
+                            Severity::Warning,
+                            block_range,
+                            format!(
+                                "unknown code block language `{lang}`, rendering it without highlighting"
+                            ),
+                            Some(format!("known languages: {}", highlight::language_names())),
+                        );
+                        highlight::render_plain(&code)
+                    }
                 };
-                let html = render_code(&lang, &code);
                 emit(Event::Html(html.into()));
-                return events;
+                return &events[1..];
             }
-            _ => {
-                panic!("unknown type in code block")
+            event => {
+                let range = problems.range_of(events);
+                problems.error(range, format!("unexpected {event:?} in code block"));
             }
         }
+        events = &events[1..];
+    }
+    problems.error(block_range, "code block is never closed".to_owned());
+    emit(Event::Html(highlight::render_plain(&code).into()));
+    events
+}
+
+/// The `status` in the front matter.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Draft,
+    /// Published, but only reachable for people with the link.
+    Unlisted,
+    Published,
+}
+
+/// Where an article shows up, see [`Metadata::visibility`].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Visibility {
+    Draft,
+    /// Published once `publish_date` is reached.
+    Scheduled,
+    Unlisted,
+    Published,
+}
+
+impl Visibility {
+    /// Whether the article page is written to the output.
+    pub fn is_written(self, include_drafts: bool) -> bool {
+        match self {
+            Visibility::Published | Visibility::Unlisted => true,
+            Visibility::Draft | Visibility::Scheduled => include_drafts,
+        }
     }
-    panic!("no end codeblock")
+
+    /// Whether the article shows up in the home page, tags, search, feeds and
+    /// sitemap.
+    pub fn is_listed(self, include_drafts: bool) -> bool {
+        match self {
+            Visibility::Published => true,
+            Visibility::Unlisted => false,
+            Visibility::Draft | Visibility::Scheduled => include_drafts,
+        }
+    }
+}
+
+/// A heading in the article, used for the table of contents.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Heading {
+    pub level: u8,
+    pub id: String,
+    pub title: String,
 }
 
 #[derive(Deserialize, Default, Clone, PartialEq, Eq)]
@@ -194,33 +186,358 @@ pub struct Metadata {
     pub tags: Vec<String>,
     #[serde(default)]
     pub title: String,
+    /// Other names to use in wikilinks to this article.
+    #[serde(default)]
+    pub aliases: Vec<String>,
     pub publish_date: Option<jiff::civil::Date>,
+    #[serde(default)]
+    pub status: Option<Status>,
+    /// Show a table of contents above the article.
+    #[serde(default)]
+    pub toc: bool,
+    /// Show footnotes in the margin on wide screens.
+    #[serde(default)]
+    pub sidenotes: bool,
+    #[serde(skip)]
+    pub headings: Vec<Heading>,
+    /// Destinations of the links in the body, with wikilinks resolved.
+    #[serde(skip)]
+    pub links: Vec<String>,
+}
+
+impl Metadata {
+    /// Without an explicit status, articles without a `publish_date` are drafts.
+    /// Articles with a `publish_date` after `today` are scheduled.
+    pub fn visibility(&self, today: jiff::civil::Date) -> Visibility {
+        match (self.status, self.publish_date) {
+            (Some(Status::Draft), _) | (None, None) => Visibility::Draft,
+            (_, Some(date)) if date > today => Visibility::Scheduled,
+            (Some(Status::Unlisted), _) => Visibility::Unlisted,
+            (Some(Status::Published), _) | (None, Some(_)) => Visibility::Published,
+        }
+    }
 }
 
-fn postprocess_events<'a>(mut events: &'a [Event<'a>]) -> (Vec<Event<'a>>, Metadata) {
+/// Turns heading text into an id, like `Signature based IR` to
+/// `signature-based-ir`.
+fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-' || c == '_') && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_matches('-');
+    if slug.is_empty() {
+        "section".to_owned()
+    } else {
+        slug.to_owned()
+    }
+}
+
+fn footnote_ref_id(number: usize, reference: usize) -> String {
+    if reference == 1 {
+        format!("fnref-{number}")
+    } else {
+        format!("fnref-{number}-{reference}")
+    }
+}
+
+fn reference_html<'a>(number: usize, ref_id: Option<&str>) -> Event<'a> {
+    let id = ref_id
+        .map(|ref_id| format!(r#" id="{ref_id}""#))
+        .unwrap_or_default();
+    Event::InlineHtml(
+        format!(r##"<sup class="footnote-ref"{id}><a href="#fn-{number}">{number}</a></sup>"##)
+            .into(),
+    )
+}
+
+/// The numbers of the footnotes in order of their first reference, and how
+/// often each is referenced.
+#[derive(Default)]
+struct FootnoteNumbers {
+    order: Vec<String>,
+    reference_counts: HashMap<String, usize>,
+}
+
+impl FootnoteNumbers {
+    fn number(&self, label: &str) -> Option<usize> {
+        self.order
+            .iter()
+            .position(|known| known == label)
+            .map(|i| i + 1)
+    }
+
+    /// Replaces the references with links to the footnotes. Also returns
+    /// where the first reference to a footnote is, with its number.
+    fn rewrite<'a>(&mut self, events: Vec<Event<'a>>) -> (Vec<Event<'a>>, Vec<(usize, usize)>) {
+        let mut output = Vec::with_capacity(events.len());
+        let mut first_references = Vec::new();
+        for event in events {
+            let Event::FootnoteReference(label) = event else {
+                output.push(event);
+                continue;
+            };
+            let number = match self.number(&label) {
+                Some(number) => number,
+                None => {
+                    self.order.push(label.to_string());
+                    first_references.push((output.len() + 1, self.order.len()));
+                    self.order.len()
+                }
+            };
+            let count = self.reference_counts.entry(label.to_string()).or_insert(0);
+            *count += 1;
+            output.push(reference_html(
+                number,
+                Some(&footnote_ref_id(number, *count)),
+            ));
+        }
+        (output, first_references)
+    }
+}
+
+/// Numbers the footnote references in order of appearance and adds the
+/// footnotes at the end, with links back to each reference. References in
+/// footnotes are numbered after the ones in the text.
+///
+/// With `sidenotes` the footnote body is also placed right after its first
+/// reference, so css can show it in the margin.
+fn render_footnotes<'a>(
+    events: Vec<Event<'a>>,
+    definitions: &HashMap<String, Vec<Event<'a>>>,
+    sidenotes: bool,
+) -> Vec<Event<'a>> {
+    let mut numbers = FootnoteNumbers::default();
+    let (mut output, first_references) = numbers.rewrite(events);
+    // this also numbers the footnotes that are only referenced by footnotes
+    let mut bodies = Vec::new();
+    while let Some(label) = numbers.order.get(bodies.len()).cloned() {
+        let body = definitions.get(&label).cloned().unwrap_or_default();
+        bodies.push(numbers.rewrite(body).0);
+    }
+
+    if sidenotes {
+        for &(index, number) in first_references.iter().rev() {
+            let Some(body) = definitions.get(&numbers.order[number - 1]) else {
+                continue;
+            };
+            // the ids of the references are already in the footnotes
+            let body = body.iter().cloned().map(|event| match event {
+                Event::FootnoteReference(label) => {
+                    reference_html(numbers.number(&label).unwrap_or_default(), None)
+                }
+                event => event,
+            });
+            let mut body_html = String::new();
+            pulldown_cmark::html::push_html(&mut body_html, body);
+            // paragraphs can't be inside the paragraph of the reference
+            let body_html = body_html.replace("<p>", "").replace("</p>", " ");
+            output.insert(
+                index,
+                Event::InlineHtml(
+                    format!(
+                        r#"<span class="sidenote"><span class="sidenote-number">{number}</span> {}</span>"#,
+                        body_html.trim()
+                    )
+                    .into(),
+                ),
+            );
+        }
+    }
+
+    if bodies.is_empty() {
+        return output;
+    }
+    let class = if sidenotes {
+        "footnotes has-sidenotes"
+    } else {
+        "footnotes"
+    };
+    output.push(Event::Html(
+        format!(r#"<section class="{class}"><ol>"#).into(),
+    ));
+    for (i, (label, mut body)) in numbers.order.iter().zip(bodies).enumerate() {
+        let number = i + 1;
+        output.push(Event::Html(format!(r#"<li id="fn-{number}">"#).into()));
+        let mut backrefs = String::new();
+        for reference in 1..=numbers.reference_counts[label] {
+            let ref_id = footnote_ref_id(number, reference);
+            backrefs.push_str(&format!(
+                r##" <a href="#{ref_id}" class="footnote-backref" aria-label="Back to reference">↩</a>"##
+            ));
+        }
+        // put the back references at the end of the last paragraph
+        match body.pop() {
+            Some(end @ Event::End(TagEnd::Paragraph)) => {
+                body.push(Event::InlineHtml(backrefs.into()));
+                body.push(end);
+            }
+            last => {
+                body.extend(last);
+                body.push(Event::Html(backrefs.into()));
+            }
+        }
+        output.extend(body);
+        output.push(Event::Html("</li>".into()));
+    }
+    output.push(Event::Html("</ol></section>".into()));
+    output
+}
+
+/// Urls that point to a file relative to the article, and not to another
+/// article.
+fn is_relative_url(url: &str) -> bool {
+    !url.is_empty()
+        && !url.contains(':')
+        && !url.starts_with(['/', '#', '?'])
+        && !url
+            .split(['#', '?'])
+            .next()
+            .unwrap_or_default()
+            .ends_with(".md")
+}
+
+/// Relative links to the markdown of another article.
+fn is_article_link(url: &str) -> bool {
+    !url.contains(':')
+        && !url.starts_with(['/', '#', '?'])
+        && url
+            .split(['#', '?'])
+            .next()
+            .unwrap_or_default()
+            .ends_with(".md")
+}
+
+/// The name of an article in urls, the file stem or the directory name for
+/// `<slug>/index.md` bundles.
+pub fn article_slug(path: &Path) -> String {
+    let name = if path.file_name() == Some(OsStr::new("index.md")) {
+        path.parent().and_then(Path::file_name)
+    } else {
+        path.file_stem()
+    };
+    name.map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn callout_names(callouts: &[Callout]) -> String {
+    callouts
+        .iter()
+        .map(|callout| callout.name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn postprocess_events<'a>(
+    mut events: &'a [Event<'a>],
+    callouts: &[Callout],
+    wikilinks: &WikiLinks,
+    assets: &HashMap<String, String>,
+    problems: &mut Problems,
+) -> (Vec<Event<'a>>, Metadata) {
     let mut new = Vec::new();
     let mut stack: Vec<(Tag<'a>, Option<Box<dyn FnOnce() -> Vec<Event<'a>>>>)> = Vec::new();
     let mut metadata = Metadata::default();
+    let mut headings = Vec::new();
+    let mut heading_ids: HashMap<String, usize> = HashMap::new();
+    // the events outside the footnote definitions currently being collected
+    let mut footnote_stack: Vec<Vec<Event<'a>>> = Vec::new();
+    let mut footnotes: HashMap<String, Vec<Event<'a>>> = HashMap::new();
+    let mut footnote_ranges: Vec<(String, Range<usize>)> = Vec::new();
     while events.len() > 0 {
         match events {
-            [Event::Start(Tag::MetadataBlock(_)), ..] => {
+            [Event::Start(Tag::MetadataBlock(_)), rest @ ..] => {
+                let block_range = problems.range_of(events);
+                let text_start = problems.range_of(rest).start;
                 let mut metadata_text = String::new();
-                for (event_i, event) in events[1..].iter().enumerate() {
+                events = rest;
+                while let [event, rest @ ..] = events {
                     match event {
                         Event::End(TagEnd::MetadataBlock(_)) => {
-                            metadata = serde_yaml::from_str(&metadata_text).unwrap();
-                            events = &events[event_i + 2..];
+                            events = rest;
                             break;
                         }
                         Event::Text(cow_str) => {
                             metadata_text.push_str(cow_str);
                         }
-                        e => panic!("unknown event in metadata {:?}", e),
+                        e => {
+                            let range = problems.range_of(events);
+                            problems.error(range, format!("unexpected {e:?} in front matter"));
+                        }
+                    }
+                    events = rest;
+                }
+                match serde_yaml::from_str(&metadata_text) {
+                    Ok(parsed) => metadata = parsed,
+                    Err(err) => {
+                        let range = match err.location() {
+                            Some(location) => {
+                                let start = text_start + location.index();
+                                start..start + 1
+                            }
+                            None => block_range,
+                        };
+                        problems.error(range, format!("invalid front matter: {err}"));
                     }
                 }
             }
             [Event::Start(Tag::CodeBlock(code_kind)), rest @ ..] => {
-                events = handle_code_block(code_kind, rest, |event| new.push(event));
+                let block_range = problems.range_of(events);
+                events = handle_code_block(code_kind, block_range, rest, problems, |event| {
+                    new.push(event)
+                });
+            }
+            [
+                Event::Start(quote_tag @ Tag::BlockQuote(Some(kind))),
+                rest @ ..,
+            ] => {
+                let name = match kind {
+                    BlockQuoteKind::Note => "note",
+                    BlockQuoteKind::Tip => "tip",
+                    BlockQuoteKind::Important => "important",
+                    BlockQuoteKind::Warning => "warning",
+                    BlockQuoteKind::Caution => "caution",
+                };
+                match callouts.iter().find(|callout| callout.name == name) {
+                    Some(callout) => {
+                        let image = match &callout.image {
+                            Some(image) => format!(
+                                r#"<img src="{}" alt="{}">"#,
+                                escape_html(image),
+                                escape_html(&callout.alt)
+                            ),
+                            None => String::new(),
+                        };
+                        new.push(Event::Html(
+                            format!(
+                                r#"<div class="{}"><p class="callout-title">{image}{}</p>"#,
+                                escape_html(&callout.class),
+                                escape_html(&callout.display_name)
+                            )
+                            .into(),
+                        ));
+                    }
+                    None => {
+                        let range = problems.range_of(events);
+                        problems.push(
+                            Severity::Error,
+                            range,
+                            format!("no callout configured for `[!{}]`", name.to_uppercase()),
+                            Some(format!("add `{name}` to callouts.yaml")),
+                        );
+                        new.push(Event::Html("<div>".into()));
+                    }
+                }
+                stack.push((
+                    quote_tag.clone(),
+                    Some(Box::new(|| vec![Event::Html("</div>".into())])),
+                ));
+                new.push(Event::Start(Tag::BlockQuote(None)));
+                events = rest
             }
             [
                 s_quote @ Event::Start(quote_tag @ Tag::BlockQuote(_)),
@@ -228,6 +545,28 @@ fn postprocess_events<'a>(mut events: &'a [Event<'a>]) -> (Vec<Event<'a>>, Metad
                 Event::Text(text),
                 rest @ ..,
             ] if text.starts_with("!") => {
+                let text_str = text.clone().into_string();
+                let (quote_type, quote_rest) = text_str.split_once(" ").unwrap_or((&text_str, ""));
+                let quote_type = quote_type.strip_prefix("!").unwrap();
+                let Some(callout) = callouts.iter().find(|callout| callout.name == quote_type)
+                else {
+                    // render it as a normal quote
+                    let range = problems.range_of(&events[2..]);
+                    problems.push(
+                        Severity::Error,
+                        range,
+                        format!("unknown quote type: {quote_type}"),
+                        Some(format!(
+                            "add it to callouts.yaml, known quote types: {}",
+                            callout_names(callouts)
+                        )),
+                    );
+                    stack.push((quote_tag.clone(), None));
+                    new.push(s_quote.clone());
+                    events = &events[1..];
+                    continue;
+                };
+
                 stack.push((quote_tag.clone(), None));
                 stack.push((
                     Tag::Paragraph,
@@ -235,18 +574,18 @@ fn postprocess_events<'a>(mut events: &'a [Event<'a>]) -> (Vec<Event<'a>>, Metad
                         vec![Event::InlineHtml(format!("</div>").into())]
                     })),
                 ));
-
-                let text = text.clone().into_string();
-                let (quote_type, quote_rest) = text.split_once(" ").unwrap_or((&text, ""));
-                let quote_type = quote_type.strip_prefix("!").unwrap();
-                let img_src = match quote_type {
-                    "bumi_question" => "/public/bumi_question.png",
-                    "bumi_leaving" => "/public/bumi_leaving.png",
-                    ty => panic!("unknown quote type: {}", ty),
-                };
                 new.push(s_quote.clone());
+                let image = match &callout.image {
+                    Some(image) => format!(
+                        r#"<img src="{}" alt="{}" title="{}">"#,
+                        escape_html(image),
+                        escape_html(&callout.alt),
+                        escape_html(&callout.display_name)
+                    ),
+                    None => String::new(),
+                };
                 new.push(Event::InlineHtml(
-                    format!(r#"<div class="cat_quote"><img src="{img_src}">"#).into(),
+                    format!(r#"<div class="{}">{image}"#, escape_html(&callout.class)).into(),
                 ));
                 new.push(s_paragraph.clone());
                 if quote_rest.len() > 0 {
@@ -254,6 +593,251 @@ fn postprocess_events<'a>(mut events: &'a [Event<'a>]) -> (Vec<Event<'a>>, Metad
                 }
                 events = rest
             }
+            [
+                Event::Start(Tag::Link {
+                    link_type: link_type @ LinkType::WikiLink { .. },
+                    dest_url,
+                    title,
+                    id,
+                }),
+                rest @ ..,
+            ] => {
+                let (target, heading) = match dest_url.split_once('#') {
+                    Some((target, heading)) => (target, Some(slugify(heading))),
+                    None => (&**dest_url, None),
+                };
+                let url = if target.trim().is_empty() {
+                    // `[[#heading]]` links within the article
+                    String::new()
+                } else {
+                    match wikilinks.resolve(target) {
+                        Resolved::Found(url) => url.to_owned(),
+                        Resolved::Missing => {
+                            let range = problems.range_of(events);
+                            problems.push(
+                                Severity::Error,
+                                range,
+                                format!("no article found for wikilink `{target}`"),
+                                Some(
+                                    "wikilinks match the file name, title or `aliases` of an article"
+                                        .to_owned(),
+                                ),
+                            );
+                            target.to_owned()
+                        }
+                        Resolved::Ambiguous(urls) => {
+                            let range = problems.range_of(events);
+                            problems.push(
+                                Severity::Error,
+                                range,
+                                format!("wikilink `{target}` matches more than one article"),
+                                Some(format!("it could be any of: {}", urls.join(", "))),
+                            );
+                            target.to_owned()
+                        }
+                    }
+                };
+                let url = match heading {
+                    Some(heading) => format!("{url}#{heading}"),
+                    None => url,
+                };
+                let tag = Tag::Link {
+                    link_type: *link_type,
+                    dest_url: url.into(),
+                    title: title.clone(),
+                    id: id.clone(),
+                };
+                stack.push((tag.clone(), None));
+                new.push(Event::Start(tag));
+                events = rest
+            }
+            [
+                Event::Start(Tag::Link {
+                    link_type,
+                    dest_url,
+                    title,
+                    id,
+                }),
+                rest @ ..,
+            ] if is_article_link(dest_url) => {
+                let split = dest_url.find(['#', '?']).unwrap_or(dest_url.len());
+                let (path, suffix) = dest_url.split_at(split);
+                let slug = article_slug(Path::new(path));
+                // the slug is also a wikilink target, but so are titles and
+                // aliases, so check that it is the article with that file name
+                let url = format!("/articles/{slug}.html");
+                let found = match wikilinks.resolve(&slug) {
+                    Resolved::Found(found) => found == url,
+                    Resolved::Ambiguous(urls) => urls.contains(&url),
+                    Resolved::Missing => false,
+                };
+                let url = if found {
+                    format!("{url}{suffix}")
+                } else {
+                    let range = problems.range_of(events);
+                    problems.push(
+                        Severity::Error,
+                        range,
+                        format!("no article found for link `{path}`"),
+                        Some("`.md` links match the file name of an article".to_owned()),
+                    );
+                    dest_url.to_string()
+                };
+                let tag = Tag::Link {
+                    link_type: *link_type,
+                    dest_url: url.into(),
+                    title: title.clone(),
+                    id: id.clone(),
+                };
+                stack.push((tag.clone(), None));
+                new.push(Event::Start(tag));
+                events = rest
+            }
+            [
+                Event::Start(tag @ (Tag::Image { dest_url, .. } | Tag::Link { dest_url, .. })),
+                rest @ ..,
+            ] if is_relative_url(dest_url) => {
+                let split = dest_url.find(['#', '?']).unwrap_or(dest_url.len());
+                let (path, suffix) = dest_url.split_at(split);
+                let name = path.strip_prefix("./").unwrap_or(path);
+                let url = match assets.get(name) {
+                    Some(url) => Some(format!("{url}{suffix}")),
+                    None if matches!(tag, Tag::Image { .. }) => {
+                        let range = problems.range_of(events);
+                        problems.push(
+                            Severity::Error,
+                            range,
+                            format!("could not find image `{name}` next to the article"),
+                            Some(
+                                "relative images are resolved for articles in `articles/<slug>/index.md`"
+                                    .to_owned(),
+                            ),
+                        );
+                        None
+                    }
+                    // other relative links are left as is
+                    None => None,
+                };
+                let tag = match (tag.clone(), url) {
+                    (
+                        Tag::Image {
+                            link_type,
+                            title,
+                            id,
+                            ..
+                        },
+                        Some(url),
+                    ) => Tag::Image {
+                        link_type,
+                        dest_url: url.into(),
+                        title,
+                        id,
+                    },
+                    (
+                        Tag::Link {
+                            link_type,
+                            title,
+                            id,
+                            ..
+                        },
+                        Some(url),
+                    ) => Tag::Link {
+                        link_type,
+                        dest_url: url.into(),
+                        title,
+                        id,
+                    },
+                    (tag, _) => tag,
+                };
+                stack.push((tag.clone(), None));
+                new.push(Event::Start(tag));
+                events = rest
+            }
+            [
+                Event::Start(
+                    heading @ Tag::Heading {
+                        level,
+                        id,
+                        classes,
+                        attrs,
+                    },
+                ),
+                rest @ ..,
+            ] => {
+                let mut title = String::new();
+                for event in rest {
+                    match event {
+                        Event::End(TagEnd::Heading(_)) => break,
+                        Event::Text(text) | Event::Code(text) => title.push_str(text),
+                        _ => {}
+                    }
+                }
+                let id = match id {
+                    Some(id) => id.to_string(),
+                    None => {
+                        let slug = slugify(&title);
+                        let count = heading_ids.entry(slug.clone()).or_insert(0);
+                        *count += 1;
+                        if *count == 1 {
+                            slug
+                        } else {
+                            format!("{slug}-{}", *count - 1)
+                        }
+                    }
+                };
+
+                stack.push((heading.clone(), None));
+                new.push(Event::Start(Tag::Heading {
+                    level: *level,
+                    id: Some(id.clone().into()),
+                    classes: classes.clone(),
+                    attrs: attrs.clone(),
+                }));
+                new.push(Event::InlineHtml(
+                    format!(r##"<a class="anchor" href="#{id}" aria-hidden="true">#</a>"##).into(),
+                ));
+                headings.push(Heading {
+                    level: *level as u8,
+                    id,
+                    title,
+                });
+                events = rest
+            }
+            [
+                Event::Start(tag @ Tag::FootnoteDefinition(label)),
+                rest @ ..,
+            ] => {
+                footnote_ranges.push((label.to_string(), problems.range_of(events)));
+                footnote_stack.push(std::mem::take(&mut new));
+                stack.push((tag.clone(), None));
+                events = rest
+            }
+            [Event::End(TagEnd::FootnoteDefinition), rest @ ..] => {
+                if let Some((Tag::FootnoteDefinition(label), _)) = stack.pop() {
+                    let outer = footnote_stack.pop().unwrap_or_default();
+                    let body = std::mem::replace(&mut new, outer);
+                    footnotes.insert(label.to_string(), body);
+                }
+                events = rest
+            }
+            [
+                event @ (Event::InlineMath(latex) | Event::DisplayMath(latex)),
+                rest @ ..,
+            ] => {
+                let style = match event {
+                    Event::DisplayMath(_) => DisplayStyle::Block,
+                    _ => DisplayStyle::Inline,
+                };
+                match latex_to_mathml(latex, style) {
+                    Ok(mathml) => new.push(Event::InlineHtml(mathml.into())),
+                    Err(err) => {
+                        let range = problems.range_of(events);
+                        problems.error(range, format!("invalid math: {err}"));
+                        new.push(Event::Code(latex.clone()));
+                    }
+                }
+                events = rest
+            }
             [event @ Event::Start(tag), rest @ ..] => {
                 stack.push((tag.clone(), None));
                 new.push(event.clone());
@@ -272,25 +856,86 @@ fn postprocess_events<'a>(mut events: &'a [Event<'a>]) -> (Vec<Event<'a>>, Metad
                 new.push(event.clone());
                 events = rest
             }
-            [] => return (new, metadata),
+            [] => break,
+        }
+    }
+    for (label, range) in footnote_ranges {
+        // footnotes can also be referenced from other footnotes
+        let referenced = new
+            .iter()
+            .chain(footnotes.values().flatten())
+            .any(|event| matches!(event, Event::FootnoteReference(l) if **l == label));
+        if !referenced {
+            problems.warning(range, format!("footnote `{label}` is never referenced"));
         }
     }
+    let new = render_footnotes(new, &footnotes, metadata.sidenotes);
+    metadata.headings = headings;
+    metadata.links = new
+        .iter()
+        .filter_map(|event| match event {
+            Event::Start(Tag::Link { dest_url, .. }) => Some(dest_url.to_string()),
+            _ => None,
+        })
+        .collect();
     (new, metadata)
 }
 
-pub fn render_article_plaintext(markdown: &str) -> (String, Metadata) {
-    let parser: pulldown_cmark::Parser = pulldown_cmark::Parser::new_with_broken_link_callback(
-        markdown,
-        pulldown_cmark::Options::ENABLE_TABLES
-            | pulldown_cmark::Options::ENABLE_STRIKETHROUGH
-            | pulldown_cmark::Options::ENABLE_TASKLISTS
-            | pulldown_cmark::Options::ENABLE_SMART_PUNCTUATION
-            | pulldown_cmark::Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
-            | pulldown_cmark::Options::ENABLE_WIKILINKS,
-        None,
+fn parser_options() -> pulldown_cmark::Options {
+    pulldown_cmark::Options::ENABLE_TABLES
+        | pulldown_cmark::Options::ENABLE_STRIKETHROUGH
+        | pulldown_cmark::Options::ENABLE_TASKLISTS
+        | pulldown_cmark::Options::ENABLE_SMART_PUNCTUATION
+        | pulldown_cmark::Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
+        | pulldown_cmark::Options::ENABLE_WIKILINKS
+        | pulldown_cmark::Options::ENABLE_FOOTNOTES
+        | pulldown_cmark::Options::ENABLE_MATH
+        | pulldown_cmark::Options::ENABLE_GFM
+}
+
+/// Readable text for a formula, for the search index. `\frac{a}{b}` becomes
+/// `frac a b`.
+fn math_plaintext(latex: &str) -> String {
+    let text: String = latex
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "+-=<>()".contains(c) {
+                c
+            } else {
+                ' '
+            }
+        })
+        .collect();
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+pub fn render_article_plaintext(markdown: &str, callouts: &[Callout]) -> (String, Metadata) {
+    let parser: pulldown_cmark::Parser =
+        pulldown_cmark::Parser::new_with_broken_link_callback(markdown, parser_options(), None);
+    let (all_events, ranges): (Vec<_>, Vec<_>) = parser.into_offset_iter().unzip();
+    // index the formulas as text instead of mathml
+    let all_events: Vec<_> = all_events
+        .into_iter()
+        .map(|event| match event {
+            Event::InlineMath(latex) | Event::DisplayMath(latex) => {
+                Event::Text(math_plaintext(&latex).into())
+            }
+            event => event,
+        })
+        .collect();
+    // problems are reported when rendering the html, and link targets are not
+    // part of the plaintext, so wikilinks don't need to resolve
+    let mut problems = Problems {
+        ranges: &ranges,
+        list: Vec::new(),
+    };
+    let (token_stream, metadata) = postprocess_events(
+        &all_events,
+        callouts,
+        &WikiLinks::default(),
+        &HashMap::new(),
+        &mut problems,
     );
-    let all_events: Vec<_> = parser.collect();
-    let (token_stream, metadata) = postprocess_events(&all_events);
     let mut output = String::new();
     for event in token_stream {
         match event {
@@ -301,16 +946,15 @@ pub fn render_article_plaintext(markdown: &str) -> (String, Metadata) {
             Event::Text(cow_str) => {
                 output.push_str(&cow_str);
             }
-            Event::Code(cow_str) => {
+            Event::Code(cow_str) | Event::InlineMath(cow_str) | Event::DisplayMath(cow_str) => {
                 output.push_str("\n");
                 output.push_str(&cow_str);
                 output.push_str("\n");
             }
             Event::Html(_) | Event::InlineHtml(_) => {}
             Event::SoftBreak => output.push_str(" "),
-            Event::HardBreak => output.push_str("\n"),
-            Event::TaskListMarker(_) => {}
-            e => panic!("TODO handle: {e:?}"),
+            Event::HardBreak | Event::Rule => output.push_str("\n"),
+            Event::TaskListMarker(_) | Event::FootnoteReference(_) => {}
         }
     }
 
@@ -319,30 +963,105 @@ pub fn render_article_plaintext(markdown: &str) -> (String, Metadata) {
     (output, metadata)
 }
 
-pub fn render_article(markdown: &str, path: PathBuf) -> (String, Metadata) {
-    let parser: pulldown_cmark::Parser = pulldown_cmark::Parser::new_with_broken_link_callback(
-        markdown,
-        pulldown_cmark::Options::ENABLE_TABLES
-            | pulldown_cmark::Options::ENABLE_STRIKETHROUGH
-            | pulldown_cmark::Options::ENABLE_TASKLISTS
-            | pulldown_cmark::Options::ENABLE_SMART_PUNCTUATION
-            | pulldown_cmark::Options::ENABLE_YAML_STYLE_METADATA_BLOCKS
-            | pulldown_cmark::Options::ENABLE_WIKILINKS,
-        None,
-    );
-    let all_events: Vec<_> = parser.collect();
-    let (token_stream, mut metadata) = postprocess_events(&all_events);
+/// Only parses the front matter, ignoring errors.
+///
+/// Errors in the front matter are reported by `render_article`.
+pub fn parse_front_matter(markdown: &str) -> Metadata {
+    let parser = pulldown_cmark::Parser::new_ext(markdown, parser_options());
+    let mut front_matter = String::new();
+    let mut in_front_matter = false;
+    for event in parser {
+        match event {
+            Event::Start(Tag::MetadataBlock(_)) => in_front_matter = true,
+            Event::End(TagEnd::MetadataBlock(_)) => break,
+            Event::Text(text) if in_front_matter => front_matter.push_str(&text),
+            _ if in_front_matter => {}
+            // the front matter is always at the start
+            _ => break,
+        }
+    }
+    serde_yaml::from_str(&front_matter).unwrap_or_default()
+}
+
+/// Renders the article body to html, also returning the problems found while
+/// rendering.
+pub fn render_article(
+    markdown: &str,
+    path: PathBuf,
+    callouts: &[Callout],
+    wikilinks: &WikiLinks,
+    assets: &HashMap<String, String>,
+) -> (String, Metadata, Vec<Problem>) {
+    let parser: pulldown_cmark::Parser =
+        pulldown_cmark::Parser::new_with_broken_link_callback(markdown, parser_options(), None);
+    let (all_events, ranges): (Vec<_>, Vec<_>) = parser.into_offset_iter().unzip();
+    let mut problems = Problems {
+        ranges: &ranges,
+        list: Vec::new(),
+    };
+    let (token_stream, mut metadata) =
+        postprocess_events(&all_events, callouts, wikilinks, assets, &mut problems);
     let mut html_output = String::new();
     pulldown_cmark::html::push_html(&mut html_output, token_stream.into_iter());
 
-    let html_output = ArticleTemplate {
-        body: &html_output,
-        metadata: &metadata,
+    metadata.path = path;
+
+    (html_output, metadata, problems.list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(markdown: &str, wikilinks: &WikiLinks) -> (String, Metadata, Vec<Problem>) {
+        render_article(
+            markdown,
+            PathBuf::from("articles/test.md"),
+            &[],
+            wikilinks,
+            &HashMap::new(),
+        )
     }
-    .render()
-    .unwrap();
 
-    metadata.path = path;
+    #[test]
+    fn md_links_point_to_the_article() {
+        let mut wikilinks = WikiLinks::default();
+        wikilinks.insert("other", "/articles/other.html");
+        let (html, metadata, problems) = render("[other](other.md#intro)", &wikilinks);
+        assert!(problems.is_empty());
+        assert!(html.contains(r#"<a href="/articles/other.html#intro">other</a>"#));
+        assert_eq!(metadata.links, ["/articles/other.html#intro"]);
+    }
+
+    #[test]
+    fn missing_md_link_is_an_error() {
+        let (html, _, problems) = render("[gone](gone.md)", &WikiLinks::default());
+        assert_eq!(problems.len(), 1);
+        assert!(html.contains(r#"href="gone.md""#));
+    }
 
-    (html_output, metadata)
+    #[test]
+    fn references_in_footnotes_are_numbered() {
+        let markdown = "text[^a]\n\n[^a]: see[^b]\n\n[^b]: nested\n";
+        let (html, _, problems) = render(markdown, &WikiLinks::default());
+        assert!(problems.is_empty());
+        assert!(
+            html.contains(
+                r##"<sup class="footnote-ref" id="fnref-2"><a href="#fn-2">2</a></sup>"##
+            )
+        );
+        assert!(html.contains(r#"<li id="fn-2">"#));
+        assert!(!html.contains(r##"href="#b""##));
+    }
+
+    #[test]
+    fn sidenote_only_at_the_first_reference() {
+        let markdown =
+            "---\ntitle: Test\ntags: []\nsidenotes: true\n---\n\none[^a] two[^a]\n\n[^a]: note\n";
+        let (html, _, problems) = render(markdown, &WikiLinks::default());
+        assert!(problems.is_empty());
+        assert_eq!(html.matches(r#"<span class="sidenote">"#).count(), 1);
+        assert!(html.contains(r#"id="fnref-1-2""#));
+        assert!(html.contains(r##"href="#fnref-1-2""##));
+    }
 }
@@ -0,0 +1,164 @@
+//! References between the files in `public/`.
+//!
+//! CSS `url(...)` and `@import` references are found automatically. In JS only
+//! string literals marked with an `/* @asset */` comment are rewritten, for
+//! example `new URL(/* @asset */ "logo.png", document.currentScript.src)`.
+//!
+//! Only the file name of a reference is replaced by the hashed name, so it
+//! keeps working relative to wherever it pointed before.
+use std::{collections::HashMap, ops::Range, path::Path};
+
+use eyre::{Error, bail};
+
+const JS_MARKER: &str = "/* @asset */";
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Reference {
+    /// Byte range of the file name in the text.
+    pub range: Range<usize>,
+    /// Name of the referenced file in `public/`.
+    pub name: String,
+}
+
+/// A reference to a file in `public/`, `url_start` is the offset of `url` in
+/// the text.
+fn reference(url_start: usize, url: &str) -> Option<Reference> {
+    let path_end = url.find(['?', '#']).unwrap_or(url.len());
+    let path = &url[..path_end];
+    if path.is_empty() || path.contains(':') || path.starts_with("//") {
+        return None;
+    }
+    let name_start = path.rfind('/').map_or(0, |index| index + 1);
+    // `public/` is flat, so anything in another directory is not an asset
+    if !matches!(&path[..name_start], "" | "./" | "/public/") {
+        return None;
+    }
+    Some(Reference {
+        range: url_start + name_start..url_start + path_end,
+        name: path[name_start..].to_owned(),
+    })
+}
+
+/// Reads a quoted string at the start of `text`, returns the offset and the
+/// contents.
+fn quoted<'a>(text: &'a str, quotes: &[char]) -> Option<(usize, &'a str)> {
+    let quote = text.chars().next().filter(|c| quotes.contains(c))?;
+    let end = text[1..].find(quote)?;
+    Some((1, &text[1..1 + end]))
+}
+
+fn css_references(css: &str) -> Vec<Reference> {
+    let mut references = Vec::new();
+    for (start, _) in css.match_indices("url(") {
+        let rest = &css[start + 4..];
+        let trimmed = rest.trim_start();
+        let offset = start + 4 + (rest.len() - trimmed.len());
+        let url = match quoted(trimmed, &['"', '\'']) {
+            Some((quote_offset, url)) => reference(offset + quote_offset, url),
+            None => trimmed
+                .find(')')
+                .and_then(|end| reference(offset, trimmed[..end].trim_end())),
+        };
+        references.extend(url);
+    }
+    for (start, _) in css.match_indices("@import") {
+        let rest = &css[start + 7..];
+        let trimmed = rest.trim_start();
+        let offset = start + 7 + (rest.len() - trimmed.len());
+        // `@import url(...)` is handled above
+        if let Some((quote_offset, url)) = quoted(trimmed, &['"', '\'']) {
+            references.extend(reference(offset + quote_offset, url));
+        }
+    }
+    references.sort_by_key(|reference| reference.range.start);
+    references
+}
+
+fn js_references(js: &str) -> Vec<Reference> {
+    let mut references = Vec::new();
+    for (start, _) in js.match_indices(JS_MARKER) {
+        let rest = &js[start + JS_MARKER.len()..];
+        let trimmed = rest.trim_start();
+        let offset = start + JS_MARKER.len() + (rest.len() - trimmed.len());
+        if let Some((quote_offset, url)) = quoted(trimmed, &['"', '\'', '`']) {
+            references.extend(reference(offset + quote_offset, url));
+        }
+    }
+    references
+}
+
+/// Whether a file can reference other assets.
+pub fn has_references(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|extension| extension.to_str()),
+        Some("css" | "js" | "mjs")
+    )
+}
+
+pub fn references(path: &Path, text: &str) -> Vec<Reference> {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("css") => css_references(text),
+        Some("js" | "mjs") => js_references(text),
+        _ => Vec::new(),
+    }
+}
+
+/// Replaces the references with the hashed names in `files`.
+pub fn rewrite_references(
+    text: &str,
+    references: &[Reference],
+    files: &HashMap<String, String>,
+) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut last = 0;
+    for reference in references {
+        let Some(hashed_name) = files.get(&reference.name) else {
+            continue;
+        };
+        output.push_str(&text[last..reference.range.start]);
+        output.push_str(hashed_name);
+        last = reference.range.end;
+    }
+    output.push_str(&text[last..]);
+    output
+}
+
+/// Orders the files so every file comes after the files it references, as
+/// their hashes are needed to rewrite it.
+pub fn dependency_order(
+    names: &[String],
+    dependencies: &HashMap<String, Vec<String>>,
+) -> Result<Vec<String>, Error> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum State {
+        Visiting,
+        Done,
+    }
+
+    fn visit(
+        name: &str,
+        dependencies: &HashMap<String, Vec<String>>,
+        states: &mut HashMap<String, State>,
+        order: &mut Vec<String>,
+    ) -> Result<(), Error> {
+        match states.get(name) {
+            Some(State::Done) => return Ok(()),
+            Some(State::Visiting) => bail!("`{name}` references itself through other assets"),
+            None => {}
+        }
+        states.insert(name.to_owned(), State::Visiting);
+        for dependency in dependencies.get(name).into_iter().flatten() {
+            visit(dependency, dependencies, states, order)?;
+        }
+        states.insert(name.to_owned(), State::Done);
+        order.push(name.to_owned());
+        Ok(())
+    }
+
+    let mut states = HashMap::new();
+    let mut order = Vec::new();
+    for name in names {
+        visit(name, dependencies, &mut states, &mut order)?;
+    }
+    Ok(order)
+}
@@ -0,0 +1,27 @@
+use crate::db::Db;
+use eyre::{Context, Error};
+use serde::Deserialize;
+
+pub const CALLOUTS_PATH: &str = "callouts.yaml";
+
+/// A character or admonition that can be used in a block quote.
+#[derive(Deserialize, Clone, PartialEq, Eq)]
+pub struct Callout {
+    pub name: String,
+    pub display_name: String,
+    pub image: Option<String>,
+    #[serde(default)]
+    pub alt: String,
+    pub class: String,
+}
+
+pub fn load_callouts(db: &dyn Db) -> Result<Vec<Callout>, Error> {
+    let callouts_path = db.options().root.join(CALLOUTS_PATH);
+    let file = db
+        .input(callouts_path)
+        .context("could not read callouts.yaml")?;
+    let text = String::from_utf8_lossy(file.text(db)).to_string();
+    let callouts: Vec<Callout> =
+        serde_yaml::from_str(&text).context("could not parse callouts.yaml")?;
+    Ok(callouts)
+}
@@ -0,0 +1,94 @@
+//! Removes files from the output directory that the build did not write.
+//!
+//! Only files written by an earlier build are removed, these are recorded in
+//! the manifest in the output directory. Other files, like a `CNAME`, are left
+//! alone.
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use eyre::{Context, Error};
+
+use crate::db::{Diagnostic, Severity};
+
+/// The files written by the last build, relative to the output directory.
+pub const MANIFEST_PATH: &str = ".manifest";
+
+/// The manifest of the last build, empty if there was none.
+pub fn load_manifest(output_path: &Path) -> Result<HashSet<PathBuf>, Error> {
+    let manifest_path = output_path.join(MANIFEST_PATH);
+    let text = match fs::read_to_string(&manifest_path) {
+        Ok(text) => text,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(HashSet::new()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("could not read {}", manifest_path.display()));
+        }
+    };
+    Ok(text
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| output_path.join(line))
+        .collect())
+}
+
+pub fn save_manifest(output_path: &Path, manifest: &HashSet<PathBuf>) -> Result<(), Error> {
+    let mut lines: Vec<String> = manifest
+        .iter()
+        .filter_map(|path| path.strip_prefix(output_path).ok())
+        .map(|path| path.display().to_string())
+        .collect();
+    lines.sort();
+    let manifest_path = output_path.join(MANIFEST_PATH);
+    fs::write(&manifest_path, lines.join("\n"))
+        .with_context(|| format!("could not write {}", manifest_path.display()))
+}
+
+/// All files in a directory and its subdirectories.
+pub fn files_in(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Error> {
+    let entries = fs::read_dir(dir).with_context(|| format!("could not read {}", dir.display()))?;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            files_in(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Removes the files of the `previous` manifest that are not in the current
+/// `manifest`, or with `dry_run` only reports them.
+pub fn remove_stale(
+    previous: &HashSet<PathBuf>,
+    manifest: &HashSet<PathBuf>,
+    dry_run: bool,
+) -> Vec<Diagnostic> {
+    let mut stale: Vec<&PathBuf> = previous.difference(manifest).collect();
+    stale.sort();
+
+    let mut diagnostics = Vec::new();
+    for path in stale {
+        if dry_run {
+            diagnostics.push(Diagnostic::new(
+                Severity::Note,
+                path,
+                "stale output file, would be removed".to_owned(),
+            ));
+            continue;
+        }
+        match fs::remove_file(path) {
+            Ok(()) => {}
+            // already removed by hand
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => diagnostics.push(Diagnostic::new(
+                Severity::Warning,
+                path,
+                format!("could not remove stale output file: {err}"),
+            )),
+        }
+    }
+    diagnostics
+}
@@ -1,5 +1,6 @@
 use std::{
     fs,
+    ops::Range,
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
     time::Duration,
@@ -19,24 +20,199 @@ use salsa::{Accumulator, Setter};
 pub trait Db: salsa::Database {
     fn input(&self, path: PathBuf) -> Result<File, Report>;
     fn dir(&self, path: PathBuf) -> Result<Dir, Report>;
+    fn options(&self) -> &BuildOptions;
+}
+
+/// Options that are fixed for the lifetime of the database.
+#[derive(Clone)]
+pub struct BuildOptions {
+    /// Directory with the articles, public files and data files.
+    pub root: PathBuf,
+    /// Directory the site is written to.
+    pub output: PathBuf,
+    /// The site config, `site.yaml` in the root by default.
+    pub config: PathBuf,
+    /// Also output and list drafts and scheduled articles, for previewing.
+    pub drafts: bool,
+    /// Used to decide if scheduled articles are published.
+    pub today: jiff::civil::Date,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+}
+
+/// Location of a diagnostic, lines and columns are 1-based.
+#[derive(Clone, Debug)]
+pub struct Span {
+    pub range: Range<usize>,
+    pub line: usize,
+    pub column: usize,
+    pub end_line: usize,
+    pub end_column: usize,
 }
 
 #[salsa::accumulator]
-pub struct Diagnostic(pub String);
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub path: PathBuf,
+    pub message: String,
+    pub span: Option<Span>,
+    pub help: Option<String>,
+}
 
 impl Diagnostic {
+    pub fn new(severity: Severity, path: &Path, message: String) -> Self {
+        Diagnostic {
+            severity,
+            path: path.to_path_buf(),
+            message,
+            span: None,
+            help: None,
+        }
+    }
+
+    /// Points the diagnostic at the byte `range` of `source`.
+    pub fn with_span(mut self, source: &str, range: Range<usize>) -> Self {
+        let (line, column) = line_column(source, range.start);
+        let (end_line, end_column) = line_column(source, range.end);
+        self.span = Some(Span {
+            range,
+            line,
+            column,
+            end_line,
+            end_column,
+        });
+        self
+    }
+
+    pub fn with_help(mut self, help: Option<String>) -> Self {
+        self.help = help;
+        self
+    }
+
+    pub fn push(self, db: &dyn Db) {
+        self.accumulate(db);
+    }
+
     pub fn push_error(db: &dyn Db, file: &Path, error: Report) {
-        Diagnostic(format!(
-            "Error in file {}: {:?}\n",
-            file.file_name()
-                .unwrap_or_else(|| "<unknown>".as_ref())
-                .to_string_lossy(),
-            error,
-        ))
-        .accumulate(db);
+        Diagnostic::new(Severity::Error, file, format!("{error:#}")).push(db);
+    }
+
+    pub fn display_path(&self) -> String {
+        if self.path.as_os_str().is_empty() {
+            return "<unknown>".to_owned();
+        }
+        let relative = std::env::current_dir()
+            .ok()
+            .and_then(|dir| self.path.strip_prefix(dir).ok().map(Path::to_path_buf));
+        relative
+            .unwrap_or_else(|| self.path.clone())
+            .display()
+            .to_string()
+    }
+
+    /// Renders the diagnostic for the terminal, with a snippet of `source`
+    /// if it is available.
+    pub fn render(&self, source: Option<&str>) -> String {
+        let mut output = format!("{}: {}\n", self.severity.as_str(), self.message);
+        let Some(span) = &self.span else {
+            output.push_str(&format!("  --> {}\n", self.display_path()));
+            if let Some(help) = &self.help {
+                output.push_str(&format!("  = help: {help}\n"));
+            }
+            return output;
+        };
+
+        let gutter = " ".repeat(span.line.to_string().len());
+        output.push_str(&format!(
+            "{gutter}--> {}:{}:{}\n",
+            self.display_path(),
+            span.line,
+            span.column
+        ));
+        if let Some(line_text) = source.and_then(|source| source.lines().nth(span.line - 1)) {
+            let width = if span.end_line == span.line {
+                span.end_column.saturating_sub(span.column).max(1)
+            } else {
+                line_text
+                    .chars()
+                    .count()
+                    .saturating_sub(span.column - 1)
+                    .max(1)
+            };
+            output.push_str(&format!("{gutter} |\n"));
+            output.push_str(&format!("{} | {line_text}\n", span.line));
+            output.push_str(&format!(
+                "{gutter} | {}{}\n",
+                " ".repeat(span.column - 1),
+                "^".repeat(width)
+            ));
+        }
+        if let Some(help) = &self.help {
+            output.push_str(&format!("{gutter} = help: {help}\n"));
+        }
+        output
+    }
+
+    /// A single line json message, similar to cargo's `--message-format=json`.
+    pub fn to_json(&self, source: Option<&str>) -> String {
+        let span = self.span.as_ref().map(|span| {
+            serde_json::json!({
+                "byte_start": span.range.start,
+                "byte_end": span.range.end,
+                "line_start": span.line,
+                "line_end": span.end_line,
+                "column_start": span.column,
+                "column_end": span.end_column,
+            })
+        });
+        serde_json::json!({
+            "reason": "diagnostic",
+            "severity": self.severity.as_str(),
+            "path": self.path,
+            "message": self.message,
+            "span": span,
+            "help": self.help,
+            "rendered": self.render(source),
+        })
+        .to_string()
+    }
+}
+
+/// Converts a byte offset into a 1-based line and column.
+pub fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
     }
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let column = before[line_start..].chars().count() + 1;
+    (line, column)
 }
 
+/// A file written to the output directory, together they are the manifest of
+/// a build.
+#[salsa::accumulator]
+#[derive(Clone, Debug)]
+pub struct Written(pub PathBuf);
+
 #[salsa::input(debug)]
 pub struct File {
     pub path: PathBuf,
@@ -73,10 +249,11 @@ pub struct BlogDatabase {
 
     pub files: DashMap<PathBuf, FileItem>,
     pub file_watcher: Option<Arc<Mutex<Debouncer<RecommendedWatcher>>>>,
+    pub options: BuildOptions,
 }
 
 impl BlogDatabase {
-    pub fn new_watch(tx: Sender<DebounceEventResult>) -> Self {
+    pub fn new_watch(options: BuildOptions, tx: Sender<DebounceEventResult>) -> Self {
         Self {
             storage: Default::default(),
             logs: Default::default(),
@@ -84,15 +261,17 @@ impl BlogDatabase {
             file_watcher: Some(Arc::new(Mutex::new(
                 new_debouncer(Duration::from_secs(1), tx).unwrap(),
             ))),
+            options,
         }
     }
 
-    pub fn new() -> Self {
+    pub fn new(options: BuildOptions) -> Self {
         Self {
             storage: Default::default(),
             logs: Default::default(),
             files: DashMap::new(),
             file_watcher: None,
+            options,
         }
     }
 
@@ -143,6 +322,10 @@ impl salsa::Database for BlogDatabase {
 
 #[salsa::db]
 impl Db for BlogDatabase {
+    fn options(&self) -> &BuildOptions {
+        &self.options
+    }
+
     fn input(&self, path: PathBuf) -> Result<File, Report> {
         let path = path
             .canonicalize()
@@ -167,8 +350,8 @@ impl Db for BlogDatabase {
                         .watcher()
                         .watch(&path, RecursiveMode::NonRecursive)
                         .unwrap();
-                    entry.insert(FileItem::File(file));
                 }
+                entry.insert(FileItem::File(file));
                 file
             }
         })
@@ -199,10 +382,45 @@ impl Db for BlogDatabase {
                         .watcher()
                         .watch(&path, RecursiveMode::NonRecursive)
                         .unwrap();
-                    entry.insert(FileItem::Dir(dir));
                 }
+                entry.insert(FileItem::Dir(dir));
                 dir
             }
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_column_is_one_based() {
+        let source = "first\nsécond\n";
+        assert_eq!(line_column(source, 0), (1, 1));
+        assert_eq!(line_column(source, 3), (1, 4));
+        assert_eq!(line_column(source, 6), (2, 1));
+        // columns count characters, not bytes
+        assert_eq!(line_column(source, 9), (2, 3));
+        // offsets inside a character or past the end are clamped
+        assert_eq!(line_column(source, 8), (2, 2));
+        assert_eq!(line_column(source, 100), (3, 1));
+    }
+
+    #[test]
+    fn render_points_at_the_span() {
+        let source = "title\nsome bad text\n";
+        let diagnostic = Diagnostic::new(Severity::Error, Path::new(""), "bad".to_owned())
+            .with_span(source, 11..14)
+            .with_help(Some("use good text".to_owned()));
+        assert_eq!(
+            diagnostic.render(Some(source)),
+            "error: bad\n \
+             --> <unknown>:2:6\n  \
+             |\n\
+             2 | some bad text\n  \
+             |      ^^^\n  \
+             = help: use good text\n"
+        );
+    }
+}
@@ -0,0 +1,284 @@
+//! Checks external urls for the `check-links` command.
+//!
+//! Results are cached in `CACHE_PATH` and reused until they are older than
+//! `CACHE_TTL`. When a url can't be reached at all, for example when offline,
+//! the last known status is kept so it can still be reported.
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use eyre::{Context, Error};
+use serde::{Deserialize, Serialize};
+
+pub const CACHE_PATH: &str = ".link-cache.json";
+pub const CACHE_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// Maximum amount of requests at the same time.
+const WORKERS: usize = 8;
+/// Minimum time between the start of two requests to the same host.
+const HOST_INTERVAL: Duration = Duration::from_millis(500);
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CheckResult {
+    /// `None` if the server could not be reached.
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    /// Seconds since the unix epoch.
+    pub checked: u64,
+}
+
+impl CheckResult {
+    pub fn is_ok(&self) -> bool {
+        matches!(self.status, Some(200..=399))
+    }
+
+    pub fn is_expired(&self, now: u64) -> bool {
+        now.saturating_sub(self.checked) > CACHE_TTL.as_secs()
+    }
+}
+
+pub type Cache = HashMap<String, CheckResult>;
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+pub fn load_cache(path: &Path) -> Result<Cache, Error> {
+    if !path.exists() {
+        return Ok(Cache::new());
+    }
+    let text =
+        fs::read_to_string(path).with_context(|| format!("could not read {}", path.display()))?;
+    serde_json::from_str(&text).with_context(|| format!("could not parse {}", path.display()))
+}
+
+pub fn save_cache(path: &Path, cache: &Cache) -> Result<(), Error> {
+    let text = serde_json::to_string_pretty(cache)?;
+    fs::write(path, text).with_context(|| format!("could not write {}", path.display()))
+}
+
+fn host(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.split(['/', '?', '#']).next().unwrap_or(rest)
+}
+
+/// Spaces out the requests to each host.
+#[derive(Default)]
+struct RateLimiter {
+    next_request: Mutex<HashMap<String, Instant>>,
+}
+
+impl RateLimiter {
+    fn wait(&self, host: &str) {
+        let now = Instant::now();
+        let start = {
+            let mut next_request = self.next_request.lock().unwrap();
+            let next = next_request.entry(host.to_owned()).or_insert(now);
+            let start = (*next).max(now);
+            *next = start + HOST_INTERVAL;
+            start
+        };
+        thread::sleep(start - now);
+    }
+}
+
+fn request(agent: &ureq::Agent, method: &str, url: &str) -> CheckResult {
+    let (status, error) = match agent.request(method, url).call() {
+        Ok(response) => (Some(response.status()), None),
+        Err(ureq::Error::Status(status, _)) => (Some(status), None),
+        Err(ureq::Error::Transport(transport)) => (None, Some(transport.to_string())),
+    };
+    CheckResult {
+        status,
+        error,
+        checked: now(),
+    }
+}
+
+fn check_url(agent: &ureq::Agent, url: &str) -> CheckResult {
+    let result = request(agent, "HEAD", url);
+    if result.is_ok() || result.status.is_none() {
+        return result;
+    }
+    // not every server supports HEAD requests
+    request(agent, "GET", url)
+}
+
+/// Checks the urls that are not in the cache or have expired, and updates the
+/// cache with the results.
+pub fn check_urls(urls: &[String], cache: &mut Cache) {
+    let now = now();
+    let queue: Vec<&str> = urls
+        .iter()
+        .filter(|url| cache.get(*url).is_none_or(|result| result.is_expired(now)))
+        .map(String::as_str)
+        .collect();
+    let workers = WORKERS.min(queue.len());
+    let queue = Mutex::new(queue);
+    let results = Mutex::new(Vec::new());
+    let limiter = RateLimiter::default();
+    let agent = ureq::AgentBuilder::new()
+        .timeout(TIMEOUT)
+        .user_agent(concat!("blog-link-checker/", env!("CARGO_PKG_VERSION")))
+        .build();
+
+    thread::scope(|scope| {
+        for _ in 0..workers {
+            scope.spawn(|| {
+                loop {
+                    let Some(url) = queue.lock().unwrap().pop() else {
+                        break;
+                    };
+                    limiter.wait(host(url));
+                    let result = check_url(&agent, url);
+                    results.lock().unwrap().push((url.to_owned(), result));
+                }
+            });
+        }
+    });
+
+    for (url, result) in results.into_inner().unwrap() {
+        if result.status.is_none() && cache.contains_key(&url) {
+            // keep the last known status
+            continue;
+        }
+        cache.insert(url, result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::TcpListener,
+        sync::Arc,
+    };
+
+    use super::*;
+
+    /// Serves `/ok`, `/missing`, `/no-head` (405 for HEAD) and `/drop` (closes
+    /// the connection without a response). Returns the base url and the
+    /// requests it received.
+    fn start_server() -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                loop {
+                    let mut header = String::new();
+                    if reader.read_line(&mut header).unwrap() == 0 || header == "\r\n" {
+                        break;
+                    }
+                }
+                let mut parts = request_line.split_whitespace();
+                let method = parts.next().unwrap_or_default().to_owned();
+                let path = parts.next().unwrap_or_default().to_owned();
+                received.lock().unwrap().push(format!("{method} {path}"));
+                let status = match (method.as_str(), path.as_str()) {
+                    (_, "/drop") => continue,
+                    (_, "/ok") | ("GET", "/no-head") => "200 OK",
+                    ("HEAD", "/no-head") => "405 Method Not Allowed",
+                    _ => "404 Not Found",
+                };
+                let response =
+                    format!("HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        (base_url, requests)
+    }
+
+    #[test]
+    fn checks_status_and_falls_back_to_get() {
+        let (base_url, requests) = start_server();
+        let urls: Vec<String> = ["/ok", "/missing", "/no-head", "/drop"]
+            .iter()
+            .map(|path| format!("{base_url}{path}"))
+            .collect();
+        let mut cache = Cache::new();
+        check_urls(&urls, &mut cache);
+
+        assert_eq!(cache[&urls[0]].status, Some(200));
+        assert!(cache[&urls[0]].is_ok());
+        assert_eq!(cache[&urls[1]].status, Some(404));
+        assert!(!cache[&urls[1]].is_ok());
+        assert_eq!(cache[&urls[2]].status, Some(200));
+        assert_eq!(cache[&urls[3]].status, None);
+        assert!(cache[&urls[3]].error.is_some());
+
+        let requests = requests.lock().unwrap();
+        assert!(requests.contains(&"HEAD /no-head".to_owned()));
+        assert!(requests.contains(&"GET /no-head".to_owned()));
+        assert!(!requests.contains(&"GET /ok".to_owned()));
+    }
+
+    #[test]
+    fn reuses_results_until_expired() {
+        let (base_url, requests) = start_server();
+        let fresh_url = format!("{base_url}/missing");
+        let expired_url = format!("{base_url}/ok");
+        let now = now();
+        let mut cache = Cache::new();
+        // the cached status is wrong, so it is visible when the result is reused
+        cache.insert(
+            fresh_url.clone(),
+            CheckResult {
+                status: Some(200),
+                error: None,
+                checked: now - 60,
+            },
+        );
+        cache.insert(
+            expired_url.clone(),
+            CheckResult {
+                status: Some(500),
+                error: None,
+                checked: now - CACHE_TTL.as_secs() - 60,
+            },
+        );
+        assert!(!cache[&fresh_url].is_expired(now));
+        assert!(cache[&expired_url].is_expired(now));
+
+        check_urls(&[fresh_url.clone(), expired_url.clone()], &mut cache);
+
+        assert_eq!(cache[&fresh_url].status, Some(200));
+        assert_eq!(cache[&expired_url].status, Some(200));
+        assert!(!cache[&expired_url].is_expired(now));
+        let requests = requests.lock().unwrap();
+        assert!(!requests.iter().any(|request| request.ends_with("/missing")));
+    }
+
+    #[test]
+    fn keeps_last_known_status_when_unreachable() {
+        let (base_url, _) = start_server();
+        let url = format!("{base_url}/drop");
+        let mut cache = Cache::new();
+        cache.insert(
+            url.clone(),
+            CheckResult {
+                status: Some(404),
+                error: None,
+                checked: 0,
+            },
+        );
+
+        check_urls(std::slice::from_ref(&url), &mut cache);
+
+        assert_eq!(cache[&url].status, Some(404));
+        assert_eq!(cache[&url].checked, 0);
+    }
+}
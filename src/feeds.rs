@@ -0,0 +1,65 @@
+//! Atom and JSON Feed generation.
+use jiff::civil::Date;
+
+pub struct Feed {
+    pub title: String,
+    pub author: String,
+    /// The html page the feed belongs to.
+    pub home_url: String,
+    /// Url of the feed itself, without extension.
+    pub feed_url: String,
+    pub entries: Vec<FeedEntry>,
+}
+
+pub struct FeedEntry {
+    pub title: String,
+    pub url: String,
+    pub date: Option<Date>,
+    pub content_html: String,
+    pub tags: Vec<String>,
+}
+
+fn timestamp(date: Date) -> String {
+    date.strftime("%Y-%m-%dT00:00:00Z").to_string()
+}
+
+impl Feed {
+    /// The date of the newest entry.
+    pub fn updated(&self) -> String {
+        let newest = self.entries.iter().filter_map(|entry| entry.date).max();
+        timestamp(newest.unwrap_or(Date::constant(1970, 1, 1)))
+    }
+}
+
+impl FeedEntry {
+    pub fn updated(&self) -> String {
+        timestamp(self.date.unwrap_or(Date::constant(1970, 1, 1)))
+    }
+}
+
+/// Renders the feed as JSON Feed 1.1, see <https://www.jsonfeed.org/version/1.1/>.
+pub fn render_json_feed(feed: &Feed) -> String {
+    let items: Vec<_> = feed
+        .entries
+        .iter()
+        .map(|entry| {
+            serde_json::json!({
+                "id": entry.url,
+                "url": entry.url,
+                "title": entry.title,
+                "content_html": entry.content_html,
+                "date_published": entry.date.map(timestamp),
+                "tags": entry.tags,
+            })
+        })
+        .collect();
+    serde_json::to_string_pretty(&serde_json::json!({
+        "version": "https://jsonfeed.org/version/1.1",
+        "title": feed.title,
+        "home_page_url": feed.home_url,
+        "feed_url": format!("{}.json", feed.feed_url),
+        "authors": [{"name": feed.author}],
+        "items": items,
+    }))
+    .unwrap()
+}
@@ -0,0 +1,240 @@
+//! Syntax highlighting for code blocks using tree-sitter.
+use std::io::Write as _;
+
+use eyre::Error;
+
+use crate::html::escape_html;
+
+const HIGHLIGHT_NAMES: &'static [&'static str] = &[
+    "attribute",
+    "boolean",
+    "carriage-return",
+    "comment",
+    "comment.documentation",
+    "constant",
+    "constant.builtin",
+    "constructor",
+    "constructor.builtin",
+    "embedded",
+    "error",
+    "escape",
+    "function",
+    "function.builtin",
+    "keyword",
+    "markup",
+    "markup.bold",
+    "markup.heading",
+    "markup.italic",
+    "markup.link",
+    "markup.link.url",
+    "markup.list",
+    "markup.list.checked",
+    "markup.list.numbered",
+    "markup.list.unchecked",
+    "markup.list.unnumbered",
+    "markup.quote",
+    "markup.raw",
+    "markup.raw.block",
+    "markup.raw.inline",
+    "markup.strikethrough",
+    "module",
+    "number",
+    "operator",
+    "property",
+    "property.builtin",
+    "punctuation",
+    "punctuation.bracket",
+    "punctuation.delimiter",
+    "punctuation.special",
+    "string",
+    "string.escape",
+    "string.regexp",
+    "string.special",
+    "string.special.symbol",
+    "tag",
+    "type",
+    "type.builtin",
+    "variable",
+    "variable.builtin",
+    "variable.member",
+    "variable.parameter",
+];
+
+const CLASS_NAMES: &'static [&'static str] = &[
+    "attribute",
+    "boolean",
+    "carriage-return",
+    "comment",
+    "comment-documentation",
+    "constant",
+    "constant-builtin",
+    "constructor",
+    "constructor-builtin",
+    "embedded",
+    "error",
+    "escape",
+    "function",
+    "function-builtin",
+    "keyword",
+    "markup",
+    "markup-bold",
+    "markup-heading",
+    "markup-italic",
+    "markup-link",
+    "markup-link-url",
+    "markup-list",
+    "markup-list-checked",
+    "markup-list-numbered",
+    "markup-list-unchecked",
+    "markup-list-unnumbered",
+    "markup-quote",
+    "markup-raw",
+    "markup-raw-block",
+    "markup-raw-inline",
+    "markup-strikethrough",
+    "module",
+    "number",
+    "operator",
+    "property",
+    "property-builtin",
+    "punctuation",
+    "punctuation-bracket",
+    "punctuation-delimiter",
+    "punctuation-special",
+    "string",
+    "string-escape",
+    "string-regexp",
+    "string-special",
+    "string-special-symbol",
+    "tag",
+    "type",
+    "type-builtin",
+    "variable",
+    "variable-builtin",
+    "variable-member",
+    "variable-parameter",
+];
+
+pub struct Language {
+    /// The name used in the highlight configuration, followed by its aliases.
+    pub names: &'static [&'static str],
+    language: fn() -> tree_sitter::Language,
+    highlights_query: &'static str,
+}
+
+/// All languages that can be used in a code fence.
+///
+/// To add a language, add its tree-sitter grammar crate as dependency and an
+/// entry here.
+pub const LANGUAGES: &[Language] = &[
+    Language {
+        names: &["rust", "rs"],
+        language: || tree_sitter_rust::LANGUAGE.into(),
+        highlights_query: tree_sitter_rust::HIGHLIGHTS_QUERY,
+    },
+    Language {
+        names: &["python", "py"],
+        language: || tree_sitter_python::LANGUAGE.into(),
+        highlights_query: tree_sitter_python::HIGHLIGHTS_QUERY,
+    },
+    Language {
+        names: &["toml"],
+        language: || tree_sitter_toml_ng::LANGUAGE.into(),
+        highlights_query: tree_sitter_toml_ng::HIGHLIGHTS_QUERY,
+    },
+    Language {
+        names: &["yaml", "yml"],
+        language: || tree_sitter_yaml::LANGUAGE.into(),
+        highlights_query: tree_sitter_yaml::HIGHLIGHTS_QUERY,
+    },
+    Language {
+        names: &["json"],
+        language: || tree_sitter_json::LANGUAGE.into(),
+        highlights_query: tree_sitter_json::HIGHLIGHTS_QUERY,
+    },
+    Language {
+        names: &["bash", "sh", "shell", "console"],
+        language: || tree_sitter_bash::LANGUAGE.into(),
+        highlights_query: tree_sitter_bash::HIGHLIGHT_QUERY,
+    },
+    Language {
+        names: &["c", "h"],
+        language: || tree_sitter_c::LANGUAGE.into(),
+        highlights_query: tree_sitter_c::HIGHLIGHT_QUERY,
+    },
+    Language {
+        names: &["javascript", "js", "mjs"],
+        language: || tree_sitter_javascript::LANGUAGE.into(),
+        highlights_query: tree_sitter_javascript::HIGHLIGHT_QUERY,
+    },
+    Language {
+        names: &["go", "golang"],
+        language: || tree_sitter_go::LANGUAGE.into(),
+        highlights_query: tree_sitter_go::HIGHLIGHTS_QUERY,
+    },
+    Language {
+        names: &["html", "htm"],
+        language: || tree_sitter_html::LANGUAGE.into(),
+        highlights_query: tree_sitter_html::HIGHLIGHTS_QUERY,
+    },
+    Language {
+        names: &["diff", "patch"],
+        language: || tree_sitter_diff::LANGUAGE.into(),
+        highlights_query: tree_sitter_diff::HIGHLIGHTS_QUERY,
+    },
+];
+
+/// Looks up a language by name or alias, ignoring case.
+///
+/// Only the first word of the fence info is used, so `rust,ignore` is rust.
+pub fn find_language(info: &str) -> Option<&'static Language> {
+    let name = info
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .next()
+        .unwrap_or("")
+        .to_lowercase();
+    LANGUAGES
+        .iter()
+        .find(|language| language.names.contains(&name.as_str()))
+}
+
+/// All names and aliases, for error messages.
+pub fn language_names() -> String {
+    LANGUAGES
+        .iter()
+        .flat_map(|language| language.names)
+        .copied()
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub fn render_code(language: &Language, code: &str) -> Result<String, Error> {
+    let mut highlighter_config = tree_sitter_highlight::HighlightConfiguration::new(
+        (language.language)(),
+        language.names[0],
+        language.highlights_query,
+        "",
+        "",
+    )?;
+    highlighter_config.configure(HIGHLIGHT_NAMES);
+    let mut highlighter = tree_sitter_highlight::Highlighter::new();
+    let highlights = highlighter.highlight(&highlighter_config, code.as_bytes(), None, |_| None)?;
+
+    let mut html_highlighter = tree_sitter_highlight::HtmlRenderer::new();
+    html_highlighter.render(highlights, code.as_bytes(), &|h, text| {
+        write!(text, r#"class="{}" "#, CLASS_NAMES[h.0]).unwrap();
+    })?;
+
+    let mut output = String::new();
+    output.push_str("<pre><code>");
+    for line in html_highlighter.lines() {
+        output.push_str(line);
+    }
+    output.push_str("</code></pre>");
+    Ok(output)
+}
+
+/// Renders code without highlighting, for unknown languages.
+pub fn render_plain(code: &str) -> String {
+    format!("<pre><code>{}</code></pre>", escape_html(code))
+}
@@ -3,15 +3,21 @@ use std::collections::HashMap;
 use eyre::Error;
 use lol_html::{HtmlRewriter, element};
 
-pub fn rewrite_html(
-    root_url: &str,
-    asset_map: &HashMap<String, String>,
-    input: &str,
-) -> Result<String, Error> {
+use crate::images::{DEFAULT_SIZES, ResponsiveImage};
+
+/// The hashed names of the files in `public/`.
+#[derive(Clone, PartialEq, Eq, Default)]
+pub struct AssetMap {
+    pub files: HashMap<String, String>,
+    pub images: HashMap<String, ResponsiveImage>,
+}
+
+pub fn rewrite_html(root_url: &str, asset_map: &AssetMap, input: &str) -> Result<String, Error> {
     let rewrite_url = |url: &str| -> Result<String, Error> {
         if url.starts_with("/public/") {
             let public_item = url.strip_prefix("/public/").unwrap();
             let hashed_item = asset_map
+                .files
                 .get(public_item)
                 .ok_or(eyre::eyre!("could not find asset: {public_item}"))?;
             let new_ref = format!("{root_url}/public/{hashed_item}");
@@ -32,7 +38,31 @@ pub fn rewrite_html(
 
                     Ok(())
                 }),
-                element!("img[src],script[src]", |el| {
+                element!("img[src]", |el| {
+                    let src = el.get_attribute("src").expect("src was required");
+                    el.set_attribute("src", &rewrite_url(&src)?)?;
+                    let image = src
+                        .strip_prefix("/public/")
+                        .and_then(|item| asset_map.images.get(item));
+                    if let Some(image) = image {
+                        el.set_attribute("srcset", &image.srcset(root_url))?;
+                        if !el.has_attribute("sizes") {
+                            el.set_attribute("sizes", DEFAULT_SIZES)?;
+                        }
+                        if !el.has_attribute("width") && !el.has_attribute("height") {
+                            el.set_attribute("width", &image.width.to_string())?;
+                            el.set_attribute("height", &image.height.to_string())?;
+                        }
+                    }
+                    if !el.has_attribute("loading") {
+                        el.set_attribute("loading", "lazy")?;
+                    }
+                    if !el.has_attribute("decoding") {
+                        el.set_attribute("decoding", "async")?;
+                    }
+                    Ok(())
+                }),
+                element!("script[src]", |el| {
                     let src = el.get_attribute("src").expect("src was required");
                     el.set_attribute("src", &rewrite_url(&src)?)?;
                     Ok(())
@@ -42,6 +72,22 @@ pub fn rewrite_html(
         },
         |c: &[u8]| output.extend_from_slice(c),
     );
-    rewriter.write(input.as_bytes()).unwrap();
-    Ok(String::from_utf8(output).unwrap())
+    rewriter.write(input.as_bytes())?;
+    rewriter.end()?;
+    Ok(String::from_utf8(output)?)
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#39;"),
+            c => output.push(c),
+        }
+    }
+    output
 }
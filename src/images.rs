@@ -0,0 +1,111 @@
+//! Responsive versions of the raster images in `public/`.
+//!
+//! Every image is resized to the `WIDTHS` smaller than itself, and each size is
+//! encoded as WebP and in the original format, keeping whichever is smaller.
+//! `rewrite_html` lists the sizes in the `srcset` of the `<img>` tags.
+use std::{io::Cursor, path::Path};
+
+use eyre::{Context, Error};
+use image::{
+    DynamicImage, ExtendedColorType, ImageFormat, codecs::webp::WebPEncoder, imageops::FilterType,
+};
+
+pub const WIDTHS: [u32; 4] = [320, 640, 960, 1280];
+
+/// Used for images that don't set their own `sizes`, matches the width of the
+/// content column.
+pub const DEFAULT_SIZES: &str = "(max-width: 800px) 100vw, 800px";
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct ResponsiveImage {
+    /// Size of the original image.
+    pub width: u32,
+    pub height: u32,
+    /// Hashed file names with their widths, smallest first.
+    pub variants: Vec<(String, u32)>,
+}
+
+impl ResponsiveImage {
+    pub fn srcset(&self, root_url: &str) -> String {
+        self.variants
+            .iter()
+            .map(|(file_name, width)| format!("{root_url}/public/{file_name} {width}w"))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// A resized image, before it is hashed.
+pub struct Variant {
+    pub width: u32,
+    pub extension: &'static str,
+    pub data: Vec<u8>,
+}
+
+fn original_format(path: &Path) -> Option<ImageFormat> {
+    match path.extension()?.to_str()?.to_lowercase().as_str() {
+        "png" => Some(ImageFormat::Png),
+        "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+        _ => None,
+    }
+}
+
+pub fn is_responsive(path: &Path) -> bool {
+    original_format(path).is_some()
+}
+
+fn encode(image: &DynamicImage, format: ImageFormat) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+    match format {
+        ImageFormat::WebP => {
+            let rgba = image.to_rgba8();
+            WebPEncoder::new_lossless(&mut data).encode(
+                &rgba,
+                rgba.width(),
+                rgba.height(),
+                ExtendedColorType::Rgba8,
+            )?;
+        }
+        // jpeg has no alpha channel
+        ImageFormat::Jpeg => DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg)?,
+        format => image.write_to(&mut Cursor::new(&mut data), format)?,
+    }
+    Ok(data)
+}
+
+/// Decodes an image and returns its size and the resized variants.
+pub fn resize_image(path: &Path, data: &[u8]) -> Result<(u32, u32, Vec<Variant>), Error> {
+    let format = original_format(path).ok_or(eyre::eyre!("not a supported image format"))?;
+    let image = image::load_from_memory_with_format(data, format)
+        .with_context(|| format!("could not decode {}", path.display()))?;
+    let (width, height) = (image.width(), image.height());
+
+    let mut widths: Vec<u32> = WIDTHS.into_iter().filter(|w| *w < width).collect();
+    if width <= WIDTHS[WIDTHS.len() - 1] {
+        widths.push(width);
+    }
+
+    let mut variants = Vec::new();
+    for variant_width in widths {
+        let resized = if variant_width == width {
+            image.clone()
+        } else {
+            let variant_height = (height as u64 * variant_width as u64 / width as u64).max(1);
+            image.resize_exact(variant_width, variant_height as u32, FilterType::Lanczos3)
+        };
+        let original = encode(&resized, format)?;
+        let webp = encode(&resized, ImageFormat::WebP)?;
+        let (extension, data) = if webp.len() < original.len() {
+            ("webp", webp)
+        } else {
+            (format.extensions_str()[0], original)
+        };
+        variants.push(Variant {
+            width: variant_width,
+            extension,
+            data,
+        });
+    }
+    Ok((width, height, variants))
+}
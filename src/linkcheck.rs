@@ -0,0 +1,193 @@
+//! Checks the links between the generated pages.
+//!
+//! Runs over the output directory after it is written, so it sees the urls as
+//! they are after `rewrite_html`, and catches links from templates too.
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use eyre::{Context, Error};
+use lol_html::{HtmlRewriter, element};
+
+use crate::{
+    clean::files_in,
+    db::{Diagnostic, Severity},
+};
+
+/// Removes `.` and `..` from a path without touching the filesystem.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            component => result.push(component),
+        }
+    }
+    result
+}
+
+struct Page {
+    /// `href` and `src` attributes.
+    references: Vec<String>,
+    ids: HashSet<String>,
+}
+
+fn parse_page(html: &str) -> Result<Page, Error> {
+    let mut references = Vec::new();
+    let mut ids = HashSet::new();
+    let mut rewriter = HtmlRewriter::new(
+        lol_html::Settings {
+            element_content_handlers: vec![
+                // one handler, as they can't both borrow `references`
+                element!(
+                    "a[href],link[href],img[src],script[src],source[src]",
+                    |el| {
+                        let attribute = match el.tag_name().as_str() {
+                            "a" | "link" => "href",
+                            _ => "src",
+                        };
+                        references.extend(el.get_attribute(attribute));
+                        Ok(())
+                    }
+                ),
+                element!("[id],a[name]", |el| {
+                    ids.extend(el.get_attribute("id"));
+                    ids.extend(el.get_attribute("name"));
+                    Ok(())
+                }),
+            ],
+            ..lol_html::Settings::new()
+        },
+        |_: &[u8]| {},
+    );
+    rewriter.write(html.as_bytes())?;
+    rewriter.end()?;
+    Ok(Page { references, ids })
+}
+
+fn levenshtein(left: &str, right: &str) -> usize {
+    let right: Vec<char> = right.chars().collect();
+    let mut previous: Vec<usize> = (0..=right.len()).collect();
+    for (i, l) in left.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, r) in right.iter().enumerate() {
+            let substitution = previous[j] + usize::from(l != *r);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[right.len()]
+}
+
+/// The candidate closest to `target`, if it is close enough to be a typo.
+fn suggestion<'a>(target: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    candidates
+        .map(|candidate| (levenshtein(target, candidate), candidate))
+        .filter(|(distance, _)| *distance <= (target.chars().count() / 3).max(2))
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// Path relative to the output directory, with `/` separators.
+fn site_path(path: &Path) -> String {
+    path.components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Checks that every `href` and `src` in the html files of `output_path` point
+/// to a file in `output_path`, and that `#fragments` point to an id in that
+/// page.
+pub fn check_links(output_path: &Path) -> Result<Vec<Diagnostic>, Error> {
+    let mut files = Vec::new();
+    files_in(output_path, &mut files)?;
+    let files: Vec<PathBuf> = files
+        .into_iter()
+        .map(|file| normalize_path(file.strip_prefix(output_path).unwrap()))
+        .collect();
+    let existing: HashSet<&Path> = files.iter().map(PathBuf::as_path).collect();
+    let site_paths: Vec<String> = files.iter().map(|file| site_path(file)).collect();
+
+    let mut pages = HashMap::new();
+    for file in &files {
+        if file.extension().is_none_or(|extension| extension != "html") {
+            continue;
+        }
+        let html = fs::read_to_string(output_path.join(file))
+            .with_context(|| format!("could not read {}", file.display()))?;
+        let page =
+            parse_page(&html).with_context(|| format!("could not parse {}", file.display()))?;
+        pages.insert(file.as_path(), page);
+    }
+
+    let mut diagnostics = Vec::new();
+    for (file, page) in &pages {
+        let page_path = output_path.join(file);
+        for reference in &page.references {
+            if reference.is_empty() || reference.contains(':') || reference.starts_with("//") {
+                // external links, `mailto:` and such
+                continue;
+            }
+            let (path, fragment) = match reference.split_once('#') {
+                Some((path, fragment)) => (path, Some(fragment)),
+                None => (reference.as_str(), None),
+            };
+            let path = path.split('?').next().unwrap_or_default();
+            let target = if path.is_empty() {
+                file.to_path_buf()
+            } else if let Some(path) = path.strip_prefix('/') {
+                normalize_path(Path::new(path))
+            } else {
+                normalize_path(&file.parent().unwrap_or(Path::new("")).join(path))
+            };
+            let target = if path.ends_with('/') || target.as_os_str().is_empty() {
+                target.join("index.html")
+            } else {
+                target
+            };
+
+            if !existing.contains(target.as_path()) {
+                let target = site_path(&target);
+                let help = suggestion(&target, site_paths.iter().map(String::as_str))
+                    .map(|found| format!("did you mean `/{found}`?"));
+                diagnostics.push(
+                    Diagnostic::new(
+                        Severity::Error,
+                        &page_path,
+                        format!("`{reference}` points to `/{target}`, which does not exist"),
+                    )
+                    .with_help(help),
+                );
+                continue;
+            }
+            let (Some(fragment), Some(target_page)) = (fragment, pages.get(target.as_path()))
+            else {
+                continue;
+            };
+            if fragment.is_empty() || target_page.ids.contains(fragment) {
+                continue;
+            }
+            let help = suggestion(fragment, target_page.ids.iter().map(String::as_str))
+                .map(|found| format!("did you mean `#{found}`?"));
+            diagnostics.push(
+                Diagnostic::new(
+                    Severity::Warning,
+                    &page_path,
+                    format!(
+                        "`{reference}` points to `#{fragment}`, which is not an id in /{}",
+                        site_path(&target)
+                    ),
+                )
+                .with_help(help),
+            );
+        }
+    }
+    diagnostics.sort_by(|left, right| left.path.cmp(&right.path));
+    Ok(diagnostics)
+}
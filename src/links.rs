@@ -1,7 +1,7 @@
 use crate::db::Db;
 use eyre::{Context, Error};
 use serde::Deserialize;
-use std::{cmp::Reverse, path::Path};
+use std::cmp::Reverse;
 
 pub const LINKS_PATH: &str = "links.yaml";
 
@@ -14,10 +14,8 @@ pub struct LinkEntry {
 }
 
 pub fn load_links(db: &dyn Db) -> Result<Vec<LinkEntry>, Error> {
-    let links_path = Path::new(LINKS_PATH);
-    let file = db
-        .input(links_path.to_path_buf())
-        .context("could not read links.yaml")?;
+    let links_path = db.options().root.join(LINKS_PATH);
+    let file = db.input(links_path).context("could not read links.yaml")?;
     let text = String::from_utf8_lossy(file.text(db)).to_string();
     let mut links: Vec<LinkEntry> =
         serde_yaml::from_str(&text).context("could not parse links.yaml")?;
@@ -0,0 +1,54 @@
+// Injected into every page by the dev server, see `src/serve.rs`.
+(() => {
+  const source = new EventSource('/__livereload');
+  source.addEventListener('reload', () => location.reload());
+  // only the stylesheet changed, swap it for the new hashed file without a reload
+  source.addEventListener('css', async () => {
+    const response = await fetch(location.href, { cache: 'no-store' });
+    const page = new DOMParser().parseFromString(await response.text(), 'text/html');
+    const updated = page.querySelectorAll('link[rel="stylesheet"]');
+    document.querySelectorAll('link[rel="stylesheet"]').forEach((link, i) => {
+      if (updated[i]) {
+        link.setAttribute('href', updated[i].getAttribute('href'));
+      }
+    });
+  });
+
+  // errors of the last compile, an empty list removes the overlay
+  source.addEventListener('diagnostics', event => {
+    document.querySelector('#__diagnostics')?.remove();
+    const diagnostics = JSON.parse(event.data);
+    if (diagnostics.length === 0) {
+      return;
+    }
+    const overlay = document.createElement('div');
+    overlay.id = '__diagnostics';
+    overlay.style.cssText = 'position: fixed; inset: 0; z-index: 1000; overflow: auto; '
+      + 'padding: 2rem; background: rgba(20, 20, 20, 0.92); color: #eee; '
+      + 'font: 14px/1.5 monospace; white-space: pre-wrap;';
+    const close = document.createElement('button');
+    close.textContent = 'close';
+    close.style.cssText = 'float: right;';
+    close.addEventListener('click', () => overlay.remove());
+    overlay.appendChild(close);
+    for (const diagnostic of diagnostics) {
+      const entry = document.createElement('div');
+      entry.style.cssText = 'margin-bottom: 1.5rem;';
+      const title = document.createElement('div');
+      title.style.cssText = `color: ${diagnostic.severity === 'error' ? '#ff6b6b' : '#ffd166'}; font-weight: bold;`;
+      title.textContent = `${diagnostic.severity}: ${diagnostic.message}`;
+      const location = document.createElement('div');
+      location.textContent = diagnostic.line
+        ? `  --> ${diagnostic.path}:${diagnostic.line}:${diagnostic.column}`
+        : `  --> ${diagnostic.path}`;
+      entry.append(title, location);
+      if (diagnostic.help) {
+        const help = document.createElement('div');
+        help.textContent = `  = help: ${diagnostic.help}`;
+        entry.appendChild(help);
+      }
+      overlay.appendChild(entry);
+    }
+    document.body.appendChild(overlay);
+  });
+})();
@@ -1,31 +1,85 @@
+use asset_refs::Reference;
 use base64ct::{Base64UrlUnpadded, Encoding};
 use crossbeam_channel::unbounded;
-use db::{Db, Diagnostic, Dir, File, Tag};
+use db::{BuildOptions, Db, Diagnostic, Dir, File, Severity, Tag, Written};
 use eyre::{Context, Error, Report};
-use html::rewrite_html;
+use html::{AssetMap, rewrite_html};
+use images::ResponsiveImage;
+use salsa::Accumulator;
 use sha1::{Digest, Sha1};
 use std::{
     cmp::Reverse,
-    collections::HashMap,
-    fs, io,
+    collections::{BTreeMap, HashMap, HashSet},
+    ffi::OsStr,
+    fs,
+    io::{self, Write},
     path::{Path, PathBuf},
+    process::ExitCode,
 };
 
-use article::{Metadata, render_article};
+use article::{
+    Metadata, Visibility, article_slug, parse_front_matter, render_article,
+    render_article_plaintext,
+};
+use callouts::{CALLOUTS_PATH, Callout, load_callouts};
+use feeds::{Feed, FeedEntry};
 use links::load_links;
+use site::{Features, SiteConfig, SiteLayout, load_site};
+use wikilinks::WikiLinks;
 
 mod article;
+mod asset_refs;
+mod callouts;
+mod clean;
 mod db;
+mod external_links;
+mod feeds;
+mod highlight;
 mod html;
+mod images;
+mod linkcheck;
 mod links;
+mod related;
+mod search;
+mod serve;
+mod site;
 mod templates;
+mod wikilinks;
+
+/// The site config, `None` if it could not be loaded.
+///
+/// Pages use the narrower `site_layout` and `site_features`, so editing one
+/// part of the config only rebuilds the pages that depend on it.
+#[salsa::tracked]
+fn site_config<'a>(db: &'a dyn Db) -> Option<SiteConfig> {
+    match load_site(db) {
+        Ok(site) => Some(site),
+        Err(err) => {
+            Diagnostic::push_error(db, &db.options().config, err);
+            None
+        }
+    }
+}
+
+/// The part of the site config used by every html page.
+#[salsa::tracked]
+fn site_layout<'a>(db: &'a dyn Db) -> SiteLayout {
+    site_config(db)
+        .map(|site| site.layout())
+        .unwrap_or_default()
+}
+
+#[salsa::tracked]
+fn site_features<'a>(db: &'a dyn Db) -> Features {
+    site_config(db)
+        .map(|site| site.features)
+        .unwrap_or_default()
+}
 
 #[salsa::tracked]
 fn article_by_tag<'a>(db: &'a dyn Db, root: Dir) -> HashMap<Tag<'a>, Vec<File>> {
     let mut tags = HashMap::new();
-    for article_path in all_articles(db, root).unwrap() {
-        let file = db.input(article_path.to_owned()).unwrap();
-        let (_html, metadata) = compile_article(db, file);
+    for (file, metadata) in listed_articles(db, root) {
         for tag_text in metadata.tags {
             let tag = Tag::new(db, tag_text);
             tags.entry(tag.to_owned()).or_insert(Vec::new()).push(file)
@@ -39,12 +93,27 @@ fn all_article_metadata<'a>(db: &'a dyn Db, root: Dir) -> Vec<(File, Metadata)>
     let mut results = Vec::new();
     for article_path in all_articles(db, root).unwrap() {
         let file = db.input(article_path.to_owned()).unwrap();
-        let (_html, metadata) = compile_article(db, file);
+        let (_html, metadata) = compile_article_body(db, root, file);
         results.push((file, metadata));
     }
     results
 }
 
+/// The articles that show up on the home page, tags, search, feeds and sitemap.
+///
+/// Articles with errors are left out, as their page is not written.
+#[salsa::tracked]
+fn listed_articles<'a>(db: &'a dyn Db, root: Dir) -> Vec<(File, Metadata)> {
+    let options = db.options();
+    all_article_metadata(db, root)
+        .into_iter()
+        .filter(|(file, metadata)| {
+            metadata.visibility(options.today).is_listed(options.drafts)
+                && compile_article_body(db, root, *file).0.is_some()
+        })
+        .collect()
+}
+
 #[salsa::tracked]
 fn tag_posts<'a>(db: &'a dyn Db, root: Dir, tag: Tag<'a>) -> Vec<File> {
     let all_tags = article_by_tag(db, root);
@@ -60,66 +129,612 @@ fn all_tags<'a>(db: &'a dyn Db, root: Dir) -> Vec<Tag<'a>> {
     tags
 }
 
-fn compile_tag<'a>(db: &'a dyn Db, root: Dir, tag: Tag) -> String {
+/// Compiles a tag page, the html is `None` if it has errors.
+fn compile_tag<'a>(db: &'a dyn Db, root: Dir, tag: Tag) -> Option<String> {
     let posts = tag_posts(db, root, tag);
 
     let mut posts = posts
         .into_iter()
         .map(|post| {
-            let (_, metadata) = compile_article(db, post);
+            let (_, metadata) = compile_article(db, root, post);
             let url = article_url(db, post);
             (metadata, url)
         })
         .collect::<Vec<_>>();
     posts.sort_by_key(|item| Reverse(item.0.publish_date));
-    let tag_html = templates::tags::render_tag_page(tag.name(db), &posts);
+    let tag_html = templates::tags::render_tag_page(&site_layout(db), tag.name(db), &posts);
     let asset_map = compile_asset_map(db);
-    let tag_html = rewrite_html("..", &asset_map, &tag_html).unwrap();
-    tag_html
+    match rewrite_html("..", &asset_map, &tag_html) {
+        Ok(tag_html) => Some(tag_html),
+        Err(err) => {
+            let path = db
+                .options()
+                .output
+                .join(tag_url(db, tag).trim_start_matches('/'));
+            Diagnostic::push_error(db, &path, err);
+            None
+        }
+    }
 }
 
 fn output_tags<'a>(db: &'a dyn Db, root: Dir, output_path: &Path) -> Result<(), Error> {
     let tags_output_path = output_path.join("tags");
     for tag in all_tags(db, root) {
         let tag_output_path = tags_output_path.join(format!("{}.html", tag.name(db)));
-        let tag_html = compile_tag(db, root, tag);
-        fs::write(tag_output_path, tag_html)?;
+        if let Some(tag_html) = compile_tag(db, root, tag) {
+            write_output(db, &tag_output_path, tag_html)?;
+        }
     }
     Ok(())
 }
 
+const PUBLIC_PATH: &str = "public";
+
+/// The files in `public/` by name, without the files that reference
+/// themselves through a cycle.
+#[salsa::tracked]
+fn public_assets<'a>(db: &'a dyn Db) -> Vec<(String, File)> {
+    let public_path = db.options().root.join(PUBLIC_PATH);
+    let public_dir = match db.dir(public_path.clone()) {
+        Ok(dir) => dir,
+        Err(err) => {
+            Diagnostic::push_error(db, &public_path, err);
+            return Vec::new();
+        }
+    };
+    let mut assets = Vec::new();
+    for path in public_dir.items(db) {
+        if path.is_dir() {
+            continue;
+        }
+        match db.input(path.clone()) {
+            Ok(file) => {
+                let name = path.file_name().unwrap().to_string_lossy().into_owned();
+                assets.push((name, file));
+            }
+            Err(err) => Diagnostic::push_error(db, path, err),
+        }
+    }
+    assets.sort_by(|left, right| left.0.cmp(&right.0));
+
+    // `hashed_asset` recurses into the references, so a cycle can't be hashed
+    let dependencies: HashMap<String, Vec<String>> = assets
+        .iter()
+        .map(|(name, file)| {
+            let references = asset_references(db, *file);
+            let names = references.into_iter().map(|reference| reference.name);
+            (name.clone(), names.collect())
+        })
+        .collect();
+    assets.retain(|(name, file)| {
+        match asset_refs::dependency_order(std::slice::from_ref(name), &dependencies) {
+            Ok(_) => true,
+            Err(err) => {
+                Diagnostic::push_error(db, &file.path(db), err);
+                false
+            }
+        }
+    });
+    assets
+}
+
+/// References from a css or js file to other files in `public/`.
+#[salsa::tracked]
+fn asset_references<'a>(db: &'a dyn Db, asset: File) -> Vec<Reference> {
+    let path = asset.path(db);
+    if !asset_refs::has_references(&path) {
+        return Vec::new();
+    }
+    match std::str::from_utf8(asset.text(db)) {
+        Ok(text) => asset_refs::references(&path, text),
+        Err(err) => {
+            Diagnostic::push_error(db, &path, Report::from(err).wrap_err("not valid utf-8"));
+            Vec::new()
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+struct HashedAsset {
+    hashed_name: String,
+    /// The contents with the references rewritten, `None` if the file is
+    /// copied as is.
+    rewritten: Option<Vec<u8>>,
+}
+
+/// Hashes an asset after rewriting its references, so the hash changes when
+/// one of the referenced files changes.
+#[salsa::tracked]
+fn hashed_asset<'a>(db: &'a dyn Db, asset: File) -> HashedAsset {
+    let path = asset.path(db);
+    let name = path.file_name().unwrap().to_string_lossy().into_owned();
+    let references = asset_references(db, asset);
+    if references.is_empty() {
+        return HashedAsset {
+            hashed_name: hashed_name(asset.text(db), &name),
+            rewritten: None,
+        };
+    }
+
+    let mut files = HashMap::new();
+    for reference in &references {
+        // `public/` is flat, so all references are next to the asset
+        match db.input(path.with_file_name(&reference.name)) {
+            Ok(dependency) => {
+                let hashed = hashed_asset(db, dependency).hashed_name;
+                files.insert(reference.name.clone(), hashed);
+            }
+            Err(err) => Diagnostic::push_error(
+                db,
+                &path,
+                err.wrap_err(format!("{name} references {}", reference.name)),
+            ),
+        }
+    }
+    // references are only found in valid utf-8
+    let text = std::str::from_utf8(asset.text(db)).unwrap_or_default();
+    let rewritten = asset_refs::rewrite_references(text, &references, &files).into_bytes();
+    HashedAsset {
+        hashed_name: hashed_name(&rewritten, &name),
+        rewritten: Some(rewritten),
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+struct ResizedImage {
+    image: ResponsiveImage,
+    /// The hashed names and contents of the variants.
+    files: Vec<(String, Vec<u8>)>,
+}
+
+#[salsa::tracked]
+fn resized_image<'a>(db: &'a dyn Db, asset: File) -> Option<ResizedImage> {
+    let path = asset.path(db);
+    if !images::is_responsive(&path) {
+        return None;
+    }
+    let (width, height, variants) = match images::resize_image(&path, asset.text(db)) {
+        Ok(result) => result,
+        Err(err) => {
+            Diagnostic::push_error(db, &path, err);
+            return None;
+        }
+    };
+    let stem = path.file_stem().unwrap().to_string_lossy();
+    let mut resized = ResizedImage {
+        image: ResponsiveImage {
+            width,
+            height,
+            variants: Vec::new(),
+        },
+        files: Vec::new(),
+    };
+    for variant in variants {
+        let variant_name = hashed_name(
+            &variant.data,
+            &format!("{stem}-{}w.{}", variant.width, variant.extension),
+        );
+        resized
+            .image
+            .variants
+            .push((variant_name.clone(), variant.width));
+        resized.files.push((variant_name, variant.data));
+    }
+    Some(resized)
+}
+
+#[salsa::tracked]
+fn compile_asset_map<'a>(db: &'a dyn Db) -> AssetMap {
+    let mut map = AssetMap::default();
+    for (name, file) in public_assets(db) {
+        map.files
+            .insert(name.clone(), hashed_asset(db, file).hashed_name);
+        if let Some(resized) = resized_image(db, file) {
+            map.images.insert(name, resized.image);
+        }
+    }
+    map
+}
+
+/// Writes the hashed files of `public/` and the resized images.
+fn output_public(db: &dyn Db, output_path: &Path) {
+    let public_output_path = output_path.join("public");
+    for (_, file) in public_assets(db) {
+        let asset = hashed_asset(db, file);
+        let data = asset.rewritten.as_deref().unwrap_or(file.text(db));
+        output_file(db, data, &public_output_path.join(&asset.hashed_name));
+        if let Some(resized) = resized_image(db, file) {
+            for (name, data) in &resized.files {
+                output_file(db, data, &public_output_path.join(name));
+            }
+        }
+    }
+}
+
+/// The front matter of an article, without rendering it.
+///
+/// Queries over all articles that `compile_article` depends on use this
+/// instead, as depending on `compile_article` would be a cycle.
+#[salsa::tracked]
+fn article_front_matter<'a>(db: &'a dyn Db, article: File) -> Metadata {
+    let data = article.text(db);
+    let text = String::from_utf8_lossy(&data).to_string();
+    parse_front_matter(&text)
+}
+
+/// Every name a wikilink can use for the articles that are written.
+#[salsa::tracked]
+fn wikilink_targets<'a>(db: &'a dyn Db, root: Dir) -> WikiLinks {
+    let options = db.options();
+    let mut targets = WikiLinks::default();
+    // errors are reported by `output_articles`
+    for article_path in all_articles(db, root).unwrap_or_default() {
+        let Ok(file) = db.input(article_path.to_owned()) else {
+            continue;
+        };
+        let metadata = article_front_matter(db, file);
+        if !metadata
+            .visibility(options.today)
+            .is_written(options.drafts)
+        {
+            continue;
+        }
+        let url = article_url(db, file);
+        targets.insert(&article_slug(&article_path), &url);
+        targets.insert(&metadata.title, &url);
+        for alias in &metadata.aliases {
+            targets.insert(alias, &url);
+        }
+    }
+    targets
+}
+
+/// The characters and admonitions in `callouts.yaml`, empty if it could not be
+/// loaded.
 #[salsa::tracked]
-fn compile_asset_map<'a>(_db: &'a dyn Db) -> HashMap<String, String> {
-    // TODO convert to salsa
-    public_dir().unwrap()
+fn callout_registry<'a>(db: &'a dyn Db) -> Vec<Callout> {
+    match load_callouts(db) {
+        Ok(callouts) => callouts,
+        Err(err) => {
+            Diagnostic::push_error(db, &db.options().root.join(CALLOUTS_PATH), err);
+            Vec::new()
+        }
+    }
 }
 
+/// Renders the body of an article, the html is `None` if it has errors.
+///
+/// Urls are not rewritten yet, as that depends on where the body is used.
 #[salsa::tracked]
-fn compile_article<'a>(db: &'a dyn Db, article: File) -> (String, Metadata) {
+fn compile_article_body<'a>(
+    db: &'a dyn Db,
+    root: Dir,
+    article: File,
+) -> (Option<String>, Metadata) {
     let data = article.text(db);
     let text = String::from_utf8_lossy(&data).to_string();
+    let path = article.path(db);
+
+    let callouts = callout_registry(db);
+    let wikilinks = wikilink_targets(db, root);
+    let slug = article_slug(&path);
+    let assets: HashMap<String, String> = article_assets(db, article)
+        .into_iter()
+        .map(|asset| {
+            (
+                asset.name,
+                format!("/articles/{slug}/{}", asset.hashed_name),
+            )
+        })
+        .collect();
+    let (body, metadata, problems) =
+        render_article(&text, path.clone(), &callouts, &wikilinks, &assets);
+    let mut has_errors = false;
+    for problem in problems {
+        has_errors |= problem.severity == Severity::Error;
+        Diagnostic::new(problem.severity, &path, problem.message)
+            .with_span(&text, problem.range)
+            .with_help(problem.help)
+            .push(db);
+    }
+    if has_errors {
+        return (None, metadata);
+    }
+    (Some(body), metadata)
+}
+
+/// Compiles an article page, the html is `None` if it has errors.
+#[salsa::tracked]
+fn compile_article<'a>(db: &'a dyn Db, root: Dir, article: File) -> (Option<String>, Metadata) {
+    let (body, metadata) = compile_article_body(db, root, article);
+    let Some(body) = body else {
+        return (None, metadata);
+    };
+    let path = article.path(db);
+
+    let banner = match (
+        metadata.visibility(db.options().today),
+        metadata.publish_date,
+    ) {
+        (Visibility::Draft, _) => Some("Draft, this article is not published".to_owned()),
+        (Visibility::Scheduled, Some(date)) => {
+            let date = date.strftime("%B %d, %Y");
+            Diagnostic::new(
+                Severity::Note,
+                &path,
+                format!("article is scheduled to be published on {date}"),
+            )
+            .push(db);
+            Some(format!(
+                "Scheduled, this article will be published on {date}"
+            ))
+        }
+        _ => None,
+    };
+    let features = site_features(db);
+    let referenced_by = if features.backlinks {
+        referenced_by(db, root, article)
+    } else {
+        Vec::new()
+    };
+    let related = if features.related_posts {
+        related_articles(db, root, article)
+    } else {
+        Vec::new()
+    };
+    let html_text = templates::article::render_article(
+        &site_layout(db),
+        &body,
+        &metadata,
+        banner.as_deref(),
+        &referenced_by,
+        &related,
+    );
 
-    let (html_text, metadata) = render_article(&text, article.path(db));
     let asset_map = compile_asset_map(db);
-    let html_text = rewrite_html("..", &asset_map, &html_text).unwrap();
-    (html_text, metadata)
+    match rewrite_html("..", &asset_map, &html_text) {
+        Ok(html_text) => (Some(html_text), metadata),
+        Err(err) => {
+            Diagnostic::push_error(db, &path, err);
+            (None, metadata)
+        }
+    }
+}
+
+/// The slug of the article an internal link points to.
+///
+/// Handles `/articles/...` urls, which is also what wikilinks resolve to, and
+/// relative `.md` links.
+fn link_target(href: &str) -> Option<String> {
+    let href = href.split(['#', '?']).next().unwrap_or_default();
+    if href.contains(':') {
+        // external links and other schemes
+        return None;
+    }
+    if let Some(name) = href.strip_prefix("/articles/") {
+        name.strip_suffix(".html").map(str::to_owned)
+    } else if href.ends_with(".md") {
+        Some(article_slug(Path::new(href)))
+    } else {
+        None
+    }
+}
+
+/// The slugs of the articles an article links to.
+#[salsa::tracked]
+fn article_links<'a>(db: &'a dyn Db, root: Dir, article: File) -> Vec<String> {
+    let (_, metadata) = compile_article_body(db, root, article);
+    let mut targets: Vec<_> = metadata
+        .links
+        .iter()
+        .filter_map(|href| link_target(href))
+        .collect();
+    targets.sort();
+    targets.dedup();
+    targets
+}
+
+/// For every linked article, the listed articles that link to it.
+#[salsa::tracked]
+fn link_graph<'a>(db: &'a dyn Db, root: Dir) -> HashMap<String, Vec<File>> {
+    let mut graph: HashMap<String, Vec<File>> = HashMap::new();
+    for (file, _) in listed_articles(db, root) {
+        let source = article_slug(&file.path(db));
+        for target in article_links(db, root, file) {
+            if target != source {
+                graph.entry(target).or_default().push(file);
+            }
+        }
+    }
+    graph
+}
+
+/// The listed articles that link to an article with their urls, newest first.
+#[salsa::tracked]
+fn referenced_by<'a>(db: &'a dyn Db, root: Dir, article: File) -> Vec<(Metadata, String)> {
+    let graph = link_graph(db, root);
+    let Some(sources) = graph.get(&article_slug(&article.path(db))) else {
+        return Vec::new();
+    };
+    let mut result: Vec<_> = listed_articles(db, root)
+        .into_iter()
+        .filter(|(file, _)| sources.contains(file))
+        .map(|(file, metadata)| (metadata, article_url(db, file)))
+        .collect();
+    result.sort_by_key(|(metadata, _)| Reverse(metadata.publish_date));
+    result
+}
+
+#[salsa::tracked]
+fn article_plaintext<'a>(db: &'a dyn Db, article: File) -> String {
+    let data = article.text(db);
+    let text = String::from_utf8_lossy(&data).to_string();
+
+    let callouts = callout_registry(db);
+    let (plaintext, _metadata) = render_article_plaintext(&text, &callouts);
+    plaintext
 }
 
 fn article_url(db: &dyn Db, post: File) -> String {
-    let mut path = post.path(db);
-    path.set_extension("");
-    let name = path.file_name().unwrap().to_str().unwrap().to_owned();
-    format!("/articles/{name}.html")
+    format!("/articles/{}.html", article_slug(&post.path(db)))
+}
+
+/// A file next to the `index.md` of an article bundle.
+#[derive(Clone, PartialEq, Eq)]
+struct BundleAsset {
+    /// Path relative to the article.
+    name: String,
+    file: File,
+    hashed_name: String,
+}
+
+/// The files of an article bundle, empty for articles that are not bundles.
+#[salsa::tracked]
+fn article_assets<'a>(db: &'a dyn Db, article: File) -> Vec<BundleAsset> {
+    let path = article.path(db);
+    let Some(bundle_path) = path.parent() else {
+        return Vec::new();
+    };
+    if path.file_name() != Some(OsStr::new("index.md")) {
+        return Vec::new();
+    }
+    let bundle = match db.dir(bundle_path.to_path_buf()) {
+        Ok(bundle) => bundle,
+        Err(err) => {
+            Diagnostic::push_error(db, &path, err);
+            return Vec::new();
+        }
+    };
+    let mut assets = Vec::new();
+    for item in bundle.items(db) {
+        if *item == path || item.is_dir() {
+            continue;
+        }
+        let file = match db.input(item.clone()) {
+            Ok(file) => file,
+            Err(err) => {
+                Diagnostic::push_error(db, item, err);
+                continue;
+            }
+        };
+        let name = item.file_name().unwrap().to_string_lossy().into_owned();
+        assets.push(BundleAsset {
+            hashed_name: hashed_name(file.text(db), &name),
+            name,
+            file,
+        });
+    }
+    assets.sort_by(|left, right| left.name.cmp(&right.name));
+    assets
+}
+
+#[salsa::tracked]
+fn article_terms<'a>(db: &'a dyn Db, article: File) -> HashMap<String, u32> {
+    related::term_counts(&article_plaintext(db, article))
+}
+
+/// For every term, the amount of listed articles it occurs in.
+#[salsa::tracked]
+fn document_frequencies<'a>(db: &'a dyn Db, root: Dir) -> HashMap<String, u32> {
+    let mut frequencies = HashMap::new();
+    for (file, _) in listed_articles(db, root) {
+        for term in article_terms(db, file).into_keys() {
+            *frequencies.entry(term).or_insert(0) += 1;
+        }
+    }
+    frequencies
+}
+
+/// For every written article, the listed articles most related to it, best
+/// first.
+///
+/// This is done for all articles at once, so the TF-IDF vectors are built
+/// once per revision instead of for every article page.
+#[salsa::tracked]
+fn all_related_articles<'a>(db: &'a dyn Db, root: Dir) -> HashMap<File, Vec<File>> {
+    let options = db.options();
+    let listed = listed_articles(db, root);
+    let frequencies = document_frequencies(db, root);
+    let by_tag = article_by_tag(db, root);
+
+    let terms: HashMap<File, HashMap<String, u32>> = all_article_metadata(db, root)
+        .into_iter()
+        .filter(|(_, metadata)| {
+            metadata
+                .visibility(options.today)
+                .is_written(options.drafts)
+        })
+        .map(|(file, _)| (file, article_terms(db, file)))
+        .collect();
+    let vectors: HashMap<File, _> = terms
+        .iter()
+        .map(|(file, terms)| (*file, related::tf_idf(terms, &frequencies, listed.len())))
+        .collect();
+
+    vectors
+        .iter()
+        .map(|(article, vector)| {
+            let mut shared_tags: HashMap<File, u32> = HashMap::new();
+            for posts in by_tag.values() {
+                if posts.contains(article) {
+                    for post in posts {
+                        *shared_tags.entry(*post).or_insert(0) += 1;
+                    }
+                }
+            }
+            let mut scored: Vec<_> = listed
+                .iter()
+                .filter(|(file, _)| file != article)
+                .filter_map(|(file, _)| {
+                    let other = vectors.get(file)?;
+                    let tags = shared_tags.get(file).copied().unwrap_or(0);
+                    let score = tags as f64 * related::TAG_WEIGHT
+                        + related::cosine_similarity(vector, other);
+                    Some((score, *file))
+                })
+                .filter(|(score, _)| *score > 0.0)
+                .collect();
+            scored.sort_by(|left, right| right.0.total_cmp(&left.0));
+            let related = scored
+                .into_iter()
+                .take(related::RELATED_POSTS)
+                .map(|(_, file)| file)
+                .collect();
+            (*article, related)
+        })
+        .collect()
+}
+
+/// The listed articles most related to an article with their urls, best first.
+#[salsa::tracked]
+fn related_articles<'a>(db: &'a dyn Db, root: Dir, article: File) -> Vec<(Metadata, String)> {
+    let listed = listed_articles(db, root);
+    all_related_articles(db, root)
+        .get(&article)
+        .into_iter()
+        .flatten()
+        .filter_map(|file| listed.iter().find(|(listed, _)| listed == file))
+        .map(|(file, metadata)| (metadata.clone(), article_url(db, *file)))
+        .collect()
 }
 
 fn tag_url(db: &dyn Db, tag: Tag) -> String {
     format!("/tags/{}.html", tag.name(db))
 }
 
-#[salsa::tracked]
-fn output_file<'a>(db: &'a dyn Db, data: &'a [u8], output_path: &'a Path) {
+/// Writes a file to the output directory and records it in the manifest.
+fn write_output(db: &dyn Db, path: &Path, data: impl AsRef<[u8]>) -> Result<(), Error> {
+    fs::write(path, data)?;
+    Written(path.to_path_buf()).accumulate(db);
+    Ok(())
+}
+
+/// Like `write_output`, but reports the error. This is not a tracked query:
+/// its memo would read no inputs and never write again, so a file removed as
+/// stale would stay missing when an edit is reverted.
+fn output_file(db: &dyn Db, data: &[u8], output_path: &Path) {
     match std::fs::write(output_path, data) {
-        Ok(_) => {}
+        Ok(_) => Written(output_path.to_path_buf()).accumulate(db),
         Err(err) => Diagnostic::push_error(
             db,
             output_path,
@@ -131,7 +746,7 @@ fn output_file<'a>(db: &'a dyn Db, data: &'a [u8], output_path: &'a Path) {
     }
 }
 
-fn output_article(db: &dyn Db, input: &Path, output_path: &Path) {
+fn output_article(db: &dyn Db, root_dir: Dir, input: &Path, output_path: &Path) {
     let file = match db.input(input.to_path_buf()) {
         Ok(file) => file,
         Err(err) => {
@@ -139,30 +754,70 @@ fn output_article(db: &dyn Db, input: &Path, output_path: &Path) {
             return;
         }
     };
-    let (html_file, _metadata) = compile_article(db, file);
-    let input_path: PathBuf = file.path(db);
-    let mut html_path = output_path.join(input_path.file_name().unwrap());
-    html_path.set_extension("html");
-
+    // errors are already reported by `compile_article`
+    let (Some(html_file), metadata) = compile_article(db, root_dir, file) else {
+        return;
+    };
+    let options = db.options();
+    if !metadata
+        .visibility(options.today)
+        .is_written(options.drafts)
+    {
+        return;
+    }
+    let slug = article_slug(&file.path(db));
+    let html_path = output_path.join(format!("{slug}.html"));
     output_file(db, html_file.as_ref(), &html_path);
+
+    let assets = article_assets(db, file);
+    if assets.is_empty() {
+        return;
+    }
+    let assets_path = output_path.join(&slug);
+    if let Err(err) = fs::create_dir_all(&assets_path) {
+        Diagnostic::push_error(db, &assets_path, err.into());
+        return;
+    }
+    for asset in assets {
+        output_file(
+            db,
+            asset.file.text(db),
+            &assets_path.join(&asset.hashed_name),
+        );
+    }
 }
 
 fn output_articles(db: &dyn Db, root_dir: Dir, output_path: &Path) -> Result<(), Error> {
     let article_output_path = output_path.join("articles");
     for input in all_articles(db, root_dir)? {
-        output_article(db, input, &article_output_path);
+        output_article(db, root_dir, &input, &article_output_path);
     }
     Ok(())
 }
 
-fn all_articles<'a>(db: &'a dyn Db, input: Dir) -> Result<&'a [PathBuf], Error> {
+/// The markdown files in `articles/`, and the `index.md` of article bundles in
+/// its subdirectories.
+fn all_articles<'a>(db: &'a dyn Db, input: Dir) -> Result<Vec<PathBuf>, Error> {
     let artitle_path = input.path(db).join("articles");
-    Ok(db.dir(artitle_path)?.items(db))
+    let mut articles = Vec::new();
+    for item in db.dir(artitle_path)?.items(db) {
+        if item.is_dir() {
+            let bundle = db.dir(item.clone())?;
+            let index = bundle
+                .items(db)
+                .iter()
+                .find(|path| path.file_name() == Some(OsStr::new("index.md")));
+            articles.extend(index.cloned());
+        } else if item.extension().is_some_and(|extension| extension == "md") {
+            articles.push(item.clone());
+        }
+    }
+    Ok(articles)
 }
 
 #[salsa::tracked]
-fn compile_home(db: &dyn Db, root_dir: Dir) -> String {
-    let mut all_articles = all_article_metadata(db, root_dir);
+fn compile_home(db: &dyn Db, root_dir: Dir) -> Option<String> {
+    let mut all_articles = listed_articles(db, root_dir);
 
     let asset_map = compile_asset_map(db);
     all_articles.sort_by(|left, right| left.1.publish_date.cmp(&right.1.publish_date).reverse());
@@ -170,25 +825,30 @@ fn compile_home(db: &dyn Db, root_dir: Dir) -> String {
         .into_iter()
         .map(|(f, md)| (md, article_url(db, f)))
         .collect();
-    let home_html = templates::home::render_home(&arg);
-    let home_html = rewrite_html(".", &asset_map, &home_html)
-        .context("coult not rewrite home")
-        .unwrap();
-    home_html
+    let home_html = templates::home::render_home(&site_layout(db), &arg);
+    match rewrite_html(".", &asset_map, &home_html) {
+        Ok(home_html) => Some(home_html),
+        Err(err) => {
+            let path = db.options().output.join("index.html");
+            Diagnostic::push_error(db, &path, err.wrap_err("could not rewrite home"));
+            None
+        }
+    }
 }
 
 fn output_home(db: &dyn Db, root_dir: Dir, output_path: &Path) -> Result<(), Error> {
     let article_output_path = output_path.join("index.html");
 
-    let home_html = compile_home(db, root_dir);
-    fs::write(article_output_path, home_html).context("could not write index.html")?;
+    if let Some(home_html) = compile_home(db, root_dir) {
+        write_output(db, &article_output_path, home_html).context("could not write index.html")?;
+    }
 
     Ok(())
 }
 
 fn compile_links(db: &dyn Db) -> Result<String, Error> {
     let links = load_links(db)?;
-    let links_html = templates::links::render_links(&links);
+    let links_html = templates::links::render_links(&site_layout(db), &links);
     let asset_map = compile_asset_map(db);
     let links_html = rewrite_html(".", &asset_map, &links_html)
         .context("could not rewrite links page")?;
@@ -197,11 +857,229 @@ fn compile_links(db: &dyn Db) -> Result<String, Error> {
 
 fn output_links(db: &dyn Db, output_path: &Path) -> Result<(), Error> {
     let links_html = compile_links(db)?;
-    fs::write(output_path.join("links.html"), links_html)
+    write_output(db, &output_path.join("links.html"), links_html)
         .context("could not write links.html")?;
     Ok(())
 }
 
+#[salsa::tracked]
+fn compile_search_index(db: &dyn Db, root_dir: Dir) -> String {
+    let mut all_articles = listed_articles(db, root_dir);
+    all_articles.sort_by(|left, right| left.1.publish_date.cmp(&right.1.publish_date).reverse());
+
+    let mut index = search::SearchIndex {
+        articles: Vec::new(),
+    };
+    for (file, metadata) in all_articles {
+        let text = article_plaintext(db, file);
+        // the search page lives in the root, so link relative to it
+        let url = format!(".{}", article_url(db, file));
+        let date = metadata
+            .publish_date
+            .map(|date| date.strftime("%B %d, %Y").to_string());
+        index
+            .articles
+            .push(search::index_entry(&metadata.title, &url, date, &text));
+    }
+    serde_json::to_string(&index).unwrap()
+}
+
+fn compile_search(db: &dyn Db) -> Result<String, Error> {
+    let search_html = templates::search::render_seach(&site_layout(db));
+    let asset_map = compile_asset_map(db);
+    let search_html =
+        rewrite_html(".", &asset_map, &search_html).context("could not rewrite search")?;
+    Ok(search_html)
+}
+
+fn output_search(db: &dyn Db, root_dir: Dir, output_path: &Path) -> Result<(), Error> {
+    let index = compile_search_index(db, root_dir);
+    write_output(db, &output_path.join("search_index.json"), index)
+        .context("could not write search_index.json")?;
+
+    let search_html = compile_search(db)?;
+    write_output(db, &output_path.join("search.html"), search_html)
+        .context("could not write search.html")?;
+    Ok(())
+}
+
+fn article_feed_entry(
+    db: &dyn Db,
+    root_dir: Dir,
+    site: &SiteConfig,
+    asset_map: &AssetMap,
+    file: File,
+    metadata: &Metadata,
+) -> Result<Option<FeedEntry>, Error> {
+    // the errors are reported for the article page
+    let (Some(body), _) = compile_article_body(db, root_dir, file) else {
+        return Ok(None);
+    };
+    let content_html = rewrite_html(&site.base_url, asset_map, &body)
+        .context("could not rewrite article for feed")?;
+    Ok(Some(FeedEntry {
+        title: metadata.title.clone(),
+        url: format!("{}{}", site.base_url, article_url(db, file)),
+        date: metadata.publish_date,
+        content_html,
+        tags: metadata.tags.clone(),
+    }))
+}
+
+/// `home_url` and `feed_url` are relative to the base url, `feed_url` without
+/// extension.
+fn article_feed(
+    db: &dyn Db,
+    root_dir: Dir,
+    site: &SiteConfig,
+    title: String,
+    home_url: &str,
+    feed_url: &str,
+    mut articles: Vec<(File, Metadata)>,
+) -> Result<Feed, Error> {
+    let asset_map = compile_asset_map(db);
+    articles.sort_by_key(|(_, metadata)| Reverse(metadata.publish_date));
+    if let Some(limit) = site.feeds.limit {
+        articles.truncate(limit);
+    }
+    let entries = articles
+        .iter()
+        .map(|(file, metadata)| article_feed_entry(db, root_dir, site, &asset_map, *file, metadata))
+        .collect::<Result<Vec<_>, Error>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+    Ok(Feed {
+        title,
+        author: site.author.clone(),
+        home_url: format!("{}{home_url}", site.base_url),
+        feed_url: format!("{}{feed_url}", site.base_url),
+        entries,
+    })
+}
+
+fn write_feed(db: &dyn Db, output_path: &Path, name: &str, feed: &Feed) -> Result<(), Error> {
+    let xml_path = output_path.join(format!("{name}.xml"));
+    write_output(db, &xml_path, templates::feed::render_atom(feed))
+        .with_context(|| format!("could not write {}", xml_path.display()))?;
+    let json_path = output_path.join(format!("{name}.json"));
+    write_output(db, &json_path, feeds::render_json_feed(feed))
+        .with_context(|| format!("could not write {}", json_path.display()))?;
+    Ok(())
+}
+
+/// Writes the atom and json feeds for all articles, each tag and the links.
+fn output_feeds(db: &dyn Db, root_dir: Dir, output_path: &Path) -> Result<(), Error> {
+    // the error is reported by `site_config`
+    let Some(site) = site_config(db) else {
+        return Ok(());
+    };
+
+    let feed = article_feed(
+        db,
+        root_dir,
+        &site,
+        site.title.clone(),
+        "/",
+        "/feed",
+        listed_articles(db, root_dir),
+    )?;
+    write_feed(db, output_path, "feed", &feed)?;
+
+    let tags = if site.feeds.tags {
+        all_tags(db, root_dir)
+    } else {
+        Vec::new()
+    };
+    for tag in tags {
+        let posts = tag_posts(db, root_dir, tag)
+            .into_iter()
+            .map(|post| (post, compile_article(db, root_dir, post).1))
+            .collect();
+        let tag_url = tag_url(db, tag);
+        let feed_url = tag_url.trim_end_matches(".html");
+        let title = format!("{} - {} posts", site.title, tag.name(db));
+        let feed = article_feed(db, root_dir, &site, title, &tag_url, feed_url, posts)?;
+        write_feed(db, &output_path.join("tags"), tag.name(db), &feed)?;
+    }
+
+    if !site.features.links {
+        return Ok(());
+    }
+    let entries = load_links(db)?
+        .into_iter()
+        .take(site.feeds.limit.unwrap_or(usize::MAX))
+        .map(|link| FeedEntry {
+            title: link.title,
+            url: link.url,
+            date: Some(link.added),
+            content_html: format!("<p>{}</p>", html::escape_html(&link.notes)),
+            tags: Vec::new(),
+        })
+        .collect();
+    let feed = Feed {
+        title: format!("{} - Links", site.title),
+        author: site.author.clone(),
+        home_url: format!("{}/links.html", site.base_url),
+        feed_url: format!("{}/links", site.base_url),
+        entries,
+    };
+    write_feed(db, output_path, "links", &feed)?;
+    Ok(())
+}
+
+/// Every public page with the date it last changed, relative to the base url.
+#[salsa::tracked]
+fn sitemap_pages<'a>(db: &'a dyn Db, root_dir: Dir) -> Vec<(String, Option<jiff::civil::Date>)> {
+    let articles = listed_articles(db, root_dir);
+    let newest = articles
+        .iter()
+        .filter_map(|(_, metadata)| metadata.publish_date)
+        .max();
+
+    let mut pages = vec![("/".to_owned(), newest)];
+    for (file, metadata) in &articles {
+        pages.push((article_url(db, *file), metadata.publish_date));
+    }
+    for tag in all_tags(db, root_dir) {
+        let newest = tag_posts(db, root_dir, tag)
+            .into_iter()
+            .filter_map(|post| compile_article(db, root_dir, post).1.publish_date)
+            .max();
+        pages.push((tag_url(db, tag), newest));
+    }
+    let features = site_features(db);
+    if features.links {
+        let links_added = load_links(db)
+            .ok()
+            .and_then(|links| links.iter().map(|link| link.added).max());
+        pages.push(("/links.html".to_owned(), links_added));
+    }
+    if features.search {
+        pages.push(("/search.html".to_owned(), None));
+    }
+    pages
+}
+
+fn output_sitemap(db: &dyn Db, root_dir: Dir, output_path: &Path) -> Result<(), Error> {
+    // the error is reported by `site_config`
+    let Some(site) = site_config(db) else {
+        return Ok(());
+    };
+    let pages = sitemap_pages(db, root_dir);
+    let sitemap = templates::sitemap::render_sitemap(&site.base_url, &pages);
+    write_output(db, &output_path.join("sitemap.xml"), sitemap)
+        .context("could not write sitemap.xml")?;
+
+    let robots = format!(
+        "User-agent: *\nAllow: /\n\nSitemap: {}/sitemap.xml\n",
+        site.base_url
+    );
+    write_output(db, &output_path.join("robots.txt"), robots)
+        .context("could not write robots.txt")?;
+    Ok(())
+}
+
 /// Computes and outputes the full output dir
 ///
 /// - articles (html + plain)
@@ -209,10 +1087,16 @@ fn output_links(db: &dyn Db, output_path: &Path) -> Result<(), Error> {
 /// - tag pages
 /// - search
 /// - index page
+/// - atom and json feeds
+/// - sitemap.xml and robots.txt
+///
+/// The optional parts are turned on and off in the site config.
 #[salsa::tracked]
 fn output_dir<'a>(db: &'a dyn Db, root_dir: Dir) {
-    let output_path = Path::new("./output");
+    let output_path = db.options().output.as_path();
+    let features = site_features(db);
 
+    output_public(db, output_path);
     if let Err(e) = output_articles(db, root_dir, output_path) {
         Diagnostic::push_error(db, Path::new(""), e);
     }
@@ -222,33 +1106,191 @@ fn output_dir<'a>(db: &'a dyn Db, root_dir: Dir) {
     if let Err(e) = output_home(db, root_dir, output_path) {
         Diagnostic::push_error(db, Path::new(""), e);
     }
-    if let Err(e) = output_links(db, output_path) {
+    if features.links
+        && let Err(e) = output_links(db, output_path)
+    {
+        Diagnostic::push_error(db, Path::new(""), e);
+    }
+    if features.search
+        && let Err(e) = output_search(db, root_dir, output_path)
+    {
         Diagnostic::push_error(db, Path::new(""), e);
     }
+    if features.feeds
+        && let Err(e) = output_feeds(db, root_dir, output_path)
+    {
+        Diagnostic::push_error(db, Path::new(""), e);
+    }
+    if features.sitemap
+        && let Err(e) = output_sitemap(db, root_dir, output_path)
+    {
+        Diagnostic::push_error(db, Path::new(""), e);
+    }
+}
+
+/// The files written by `output_dir` in this revision.
+fn output_manifest(db: &dyn Db, root: Dir) -> HashSet<PathBuf> {
+    output_dir::accumulated::<Written>(db, root)
+        .into_iter()
+        .map(|written| written.0.clone())
+        .collect()
+}
+
+/// Removes the output files that were written by the `previous` build, but
+/// not in this revision. Without a `previous` manifest in memory it is read
+/// from the output directory.
+fn clean_output(
+    options: &BuildOptions,
+    manifest: &HashSet<PathBuf>,
+    previous: Option<&HashSet<PathBuf>>,
+    dry_run: bool,
+) -> Vec<Diagnostic> {
+    let output_path = &options.output;
+    let error = |message: String| vec![Diagnostic::new(Severity::Error, output_path, message)];
+    // removing files from the sources would be very bad
+    if output_path
+        .canonicalize()
+        .is_ok_and(|output| options.root.starts_with(output))
+    {
+        return error("not cleaning the output, it contains the root".to_owned());
+    }
+    let previous = match previous {
+        Some(previous) => previous.clone(),
+        None => match clean::load_manifest(output_path) {
+            Ok(previous) => previous,
+            Err(err) => return error(format!("could not clean the output: {err:#}")),
+        },
+    };
+    let mut diagnostics = clean::remove_stale(&previous, manifest, dry_run);
+    // with `dry_run` nothing is removed, so the old manifest stays valid
+    if !dry_run && let Err(err) = clean::save_manifest(output_path, manifest) {
+        diagnostics.extend(error(format!("{err:#}")));
+    }
+    diagnostics
+}
+
+/// Checks the links in the output, which can only be done once all of it is
+/// written.
+fn check_output_links(output_path: &Path) -> Vec<Diagnostic> {
+    match linkcheck::check_links(output_path) {
+        Ok(diagnostics) => diagnostics,
+        Err(err) => vec![Diagnostic::new(
+            Severity::Error,
+            output_path,
+            format!("could not check links: {err:#}"),
+        )],
+    }
 }
 
-fn main_watch() -> Result<(), Error> {
+/// The source shown in the snippet of the diagnostic.
+fn diagnostic_source(db: &dyn Db, diagnostic: &Diagnostic) -> Option<String> {
+    // only read the source when it is shown, diagnostics can also be about
+    // output files, which should not be watched
+    diagnostic
+        .span
+        .as_ref()
+        .and_then(|_| db.input(diagnostic.path.clone()).ok())
+        .map(|file| String::from_utf8_lossy(file.text(db)).to_string())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum MessageFormat {
+    Human,
+    Json,
+}
+
+/// Prints the diagnostics, returns if any of them is an error.
+fn report_diagnostics(db: &dyn Db, diagnostics: &[&Diagnostic], format: MessageFormat) -> bool {
+    let has_errors = diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error);
+    for diagnostic in diagnostics {
+        let source = diagnostic_source(db, diagnostic);
+        match format {
+            MessageFormat::Human => println!("{}", diagnostic.render(source.as_deref())),
+            MessageFormat::Json => println!("{}", diagnostic.to_json(source.as_deref())),
+        }
+    }
+    match format {
+        MessageFormat::Human if diagnostics.is_empty() => println!("compiled"),
+        MessageFormat::Human => {}
+        MessageFormat::Json => println!(
+            "{}",
+            serde_json::json!({"reason": "build-finished", "success": !has_errors})
+        ),
+    }
+    has_errors
+}
+
+/// The errors and warnings shown in the overlay of the dev server, empty if
+/// there are no errors so the overlay is cleared.
+fn overlay_json(diagnostics: &[&Diagnostic]) -> String {
+    let has_errors = diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error);
+    let entries: Vec<_> = diagnostics
+        .iter()
+        .filter(|diagnostic| has_errors && diagnostic.severity != Severity::Note)
+        .map(|diagnostic| {
+            serde_json::json!({
+                "severity": diagnostic.severity.as_str(),
+                "path": diagnostic.display_path(),
+                "line": diagnostic.span.as_ref().map(|span| span.line),
+                "column": diagnostic.span.as_ref().map(|span| span.column),
+                "message": diagnostic.message,
+                "help": diagnostic.help,
+            })
+        })
+        .collect();
+    serde_json::Value::Array(entries).to_string()
+}
+
+/// Rebuilds when the inputs change, and tells the pages open in the dev
+/// `server` to reload.
+fn main_watch(
+    options: BuildOptions,
+    message_format: MessageFormat,
+    dry_run: bool,
+    server: Option<&serve::Server>,
+) -> Result<ExitCode, Error> {
     let (tx, rx) = unbounded();
-    let mut db = db::BlogDatabase::new_watch(tx);
+    let output_path = options.output.clone();
+    let mut db = db::BlogDatabase::new_watch(options, tx);
 
-    let root = db.dir(Path::new(".").to_path_buf())?;
+    let root = db.dir(db.options().root.clone())?;
+    let mut previous_manifest = None;
+    let mut changed: Vec<PathBuf> = Vec::new();
 
     loop {
         output_dir(&db, root);
 
-        let diagnostics = output_dir::accumulated::<Diagnostic>(&db, root);
-        if diagnostics.is_empty() {
-            println!("compiled");
-        } else {
-            for diagnostic in diagnostics {
-                println!("{}", diagnostic.0);
-            }
-        }
+        let mut diagnostics = output_dir::accumulated::<Diagnostic>(&db, root);
+        let manifest = output_manifest(&db, root);
+        let clean_diagnostics =
+            clean_output(db.options(), &manifest, previous_manifest.as_ref(), dry_run);
+        diagnostics.extend(&clean_diagnostics);
+        previous_manifest = Some(manifest);
+        let link_diagnostics = check_output_links(&output_path);
+        diagnostics.extend(&link_diagnostics);
+        let has_errors = report_diagnostics(&db, &diagnostics, message_format);
 
         for log in db.logs.lock().unwrap().drain(..) {
             eprintln!("{log}");
         }
 
+        if let Some(server) = server {
+            server.set_diagnostics(&overlay_json(&diagnostics));
+        }
+        // keep the page with the overlay until the errors are fixed
+        if let Some(server) = server.filter(|_| !has_errors) {
+            let css_only = !changed.is_empty()
+                && changed
+                    .iter()
+                    .all(|path| path.extension().is_some_and(|extension| extension == "css"));
+            server.send(if css_only { "css" } else { "reload" }, "");
+        }
+        changed.clear();
+
         // Wait for file change events, the output can't change unless the
         // inputs change.
         for event in rx.recv()?.unwrap() {
@@ -256,7 +1298,13 @@ fn main_watch() -> Result<(), Error> {
             let path = match event.path.canonicalize() {
                 Ok(p) => p,
                 Err(err) => {
+                    // a removed file, drop it from its directory
                     if err.kind() == io::ErrorKind::NotFound {
+                        let parent = event.path.parent().map(Path::canonicalize);
+                        if let Some(Ok(parent)) = parent {
+                            db.reload_path(&parent)?;
+                            changed.push(event.path);
+                        }
                         continue;
                     }
                     return Err(err).with_context(|| {
@@ -265,97 +1313,429 @@ fn main_watch() -> Result<(), Error> {
                 }
             };
             db.reload_path(&path)?;
+            changed.push(path);
         }
     }
 }
 
-fn main_salsa() -> Result<(), Error> {
-    let mode = std::env::args()
-        .skip(1)
-        .next()
-        .unwrap_or_else(|| "run".to_owned());
+/// Every external url in the links and articles, with the files they are in.
+fn external_urls(db: &dyn Db, root: Dir) -> Result<BTreeMap<String, Vec<PathBuf>>, Error> {
+    let mut urls: BTreeMap<String, Vec<PathBuf>> = BTreeMap::new();
+    let links = if site_features(db).links {
+        load_links(db)?
+    } else {
+        Vec::new()
+    };
+    for link in links {
+        urls.entry(link.url)
+            .or_default()
+            .push(db.options().root.join(links::LINKS_PATH));
+    }
+    for (file, metadata) in all_article_metadata(db, root) {
+        for href in metadata.links {
+            if href.starts_with("http://") || href.starts_with("https://") {
+                let url = href.split('#').next().unwrap_or_default().to_owned();
+                let sources = urls.entry(url).or_default();
+                if !sources.contains(&file.path(db)) {
+                    sources.push(file.path(db));
+                }
+            }
+        }
+    }
+    Ok(urls)
+}
+
+/// Checks the external urls, with `offline` only the cached results are used.
+fn main_check_links(
+    options: BuildOptions,
+    message_format: MessageFormat,
+    offline: bool,
+) -> Result<ExitCode, Error> {
+    let db = db::BlogDatabase::new(options);
+    let root = db.dir(db.options().root.clone())?;
+
+    let urls = external_urls(&db, root)?;
+    let cache_path = db.options().root.join(external_links::CACHE_PATH);
+    let mut cache = external_links::load_cache(&cache_path)?;
+    if !offline {
+        let all_urls: Vec<String> = urls.keys().cloned().collect();
+        external_links::check_urls(&all_urls, &mut cache);
+        external_links::save_cache(&cache_path, &cache)?;
+    }
+
+    let now = external_links::now();
+    let mut diagnostics = Vec::new();
+    for (url, sources) in &urls {
+        let (severity, message, result) = match cache.get(url) {
+            None => (Severity::Note, format!("`{url}` was never checked"), None),
+            Some(result) if result.is_ok() => continue,
+            Some(result) => match (result.status, &result.error) {
+                (Some(status), _) => (
+                    Severity::Warning,
+                    format!("`{url}` returned status {status}"),
+                    Some(result),
+                ),
+                (None, error) => (
+                    Severity::Warning,
+                    format!(
+                        "could not reach `{url}`: {}",
+                        error.as_deref().unwrap_or("unknown error")
+                    ),
+                    Some(result),
+                ),
+            },
+        };
+        let help = result
+            .filter(|result| result.is_expired(now))
+            .map(|result| {
+                let checked = jiff::Timestamp::from_second(result.checked as i64)
+                    .map(|checked| checked.strftime("%B %d, %Y").to_string())
+                    .unwrap_or_default();
+                format!("last checked on {checked}, run without --offline to check again")
+            });
+        for source in sources {
+            diagnostics
+                .push(Diagnostic::new(severity, source, message.clone()).with_help(help.clone()));
+        }
+    }
+    let diagnostics: Vec<_> = diagnostics.iter().collect();
+    let has_errors = report_diagnostics(&db, &diagnostics, message_format);
+    Ok(exit_code(has_errors))
+}
+
+const USAGE: &str = "\
+Usage: site <command> [options]
+
+Commands:
+  build            Write the site to the output directory
+  watch            Rebuild the site when the inputs change
+  serve            Rebuild on changes and serve the site with live reload
+  check            Build the site into a temporary directory and check it
+  check-links      Check the external links in the links page and articles
+  new <title>      Create a draft article
+  render <file>    Print the html of a single article
+
+Options:
+  --root <dir>               Directory with the site sources [default: .]
+  --output <dir>             Directory to write the site to [default: <root>/output,
+                             a temporary directory for `check`]
+  --config <file>            The site config [default: <root>/site.yaml]
+  --drafts                   Also output drafts and scheduled articles
+  --message-format <format>  Print diagnostics as `human` or `json` [default: human]
+  --dry-run                  Report stale output files instead of removing them
+  --offline                  Only use the cached results in `check-links`
+  --port <port>              Port to serve the site on [default: 8000]
+  -h, --help                 Print this help
+";
+
+fn exit_code(has_errors: bool) -> ExitCode {
+    if has_errors {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+fn create_output_dirs(output_path: &Path) -> Result<(), Error> {
+    for dir in ["articles", "tags", "public"] {
+        fs::create_dir_all(output_path.join(dir)).context("could not create output dir")?;
+    }
+    Ok(())
+}
+
+/// Writes the site once, fails if there are errors so broken sites are not
+/// deployed.
+fn main_build(
+    options: BuildOptions,
+    message_format: MessageFormat,
+    dry_run: bool,
+) -> Result<ExitCode, Error> {
+    let output_path = options.output.clone();
+    let db = db::BlogDatabase::new(options);
+
+    let root = db.dir(db.options().root.clone())?;
+
+    output_dir(&db, root);
+
+    let mut diagnostics = output_dir::accumulated::<Diagnostic>(&db, root);
+    let manifest = output_manifest(&db, root);
+    let clean_diagnostics = clean_output(db.options(), &manifest, None, dry_run);
+    diagnostics.extend(&clean_diagnostics);
+    let link_diagnostics = check_output_links(&output_path);
+    diagnostics.extend(&link_diagnostics);
+    let has_errors = report_diagnostics(&db, &diagnostics, message_format);
+
+    for log in db.logs.lock().unwrap().drain(..) {
+        eprintln!("{log}");
+    }
+    Ok(exit_code(has_errors))
+}
+
+/// Builds the site without touching the real output, so errors can be found
+/// before deploying. The output is removed again unless `--output` was given.
+fn main_check(
+    options: BuildOptions,
+    message_format: MessageFormat,
+    dry_run: bool,
+    keep_output: bool,
+) -> Result<ExitCode, Error> {
+    let output_path = options.output.clone();
+    create_output_dirs(&output_path)?;
+    let exit_code = main_build(options, message_format, dry_run);
+    if !keep_output {
+        fs::remove_dir_all(&output_path)
+            .with_context(|| format!("could not remove {}", output_path.display()))?;
+    }
+    exit_code
+}
 
-    match mode.as_str() {
-        "run" => {
-            let db = db::BlogDatabase::new();
+/// Creates a draft article in `articles/`, named after today and the `title`.
+fn main_new(options: &BuildOptions, title: &str) -> Result<ExitCode, Error> {
+    let words: Vec<String> = title
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_owned)
+        .collect();
+    if words.is_empty() {
+        eyre::bail!("the title needs at least one letter or digit");
+    }
+    let name = format!("{}_{}.md", options.today, words.join("_"));
+    let path = options.root.join("articles").join(name);
 
-            let root = db.dir(Path::new(".").to_path_buf())?;
+    // a json string is also a yaml string, this takes care of the escaping
+    let front_matter = format!(
+        "---\ntitle: {}\n# publish_date: {}\ntags: []\n---\n\n",
+        serde_json::to_string(title)?,
+        options.today
+    );
+    fs::File::create_new(&path)
+        .and_then(|mut file| file.write_all(front_matter.as_bytes()))
+        .with_context(|| format!("could not create {}", path.display()))?;
+    println!("{}", path.display());
+    Ok(ExitCode::SUCCESS)
+}
 
-            output_dir(&db, root);
+/// Prints the html of the article at `path` to stdout and its diagnostics to
+/// stderr.
+fn main_render(options: BuildOptions, path: &Path) -> Result<ExitCode, Error> {
+    let db = db::BlogDatabase::new(options);
+    let root = db.dir(db.options().root.clone())?;
+    let article = db.input(path.to_path_buf())?;
 
-            let diagnostics = output_dir::accumulated::<Diagnostic>(&db, root);
-            if diagnostics.is_empty() {
-                println!("compiled");
-            } else {
-                for diagnostic in diagnostics {
-                    println!("{}", diagnostic.0);
+    let (html, _) = compile_article(&db, root, article);
+    let diagnostics = compile_article::accumulated::<Diagnostic>(&db, root, article);
+    for diagnostic in &diagnostics {
+        let source = diagnostic_source(&db, diagnostic);
+        eprintln!("{}", diagnostic.render(source.as_deref()));
+    }
+    if let Some(html) = html {
+        print!("{html}");
+    }
+    let has_errors = diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error);
+    Ok(exit_code(has_errors))
+}
+
+/// The value of a `--flag value` or `--flag=value` argument.
+fn flag_value(
+    flag: &str,
+    inline: Option<&str>,
+    args: &mut impl Iterator<Item = String>,
+) -> Result<String, Error> {
+    inline
+        .map(str::to_owned)
+        .or_else(|| args.next())
+        .ok_or_else(|| eyre::eyre!("{flag} needs a value, see --help"))
+}
+
+fn main_salsa() -> Result<ExitCode, Error> {
+    let mut positional = Vec::new();
+    let mut root = PathBuf::from(".");
+    let mut output = None;
+    let mut config = None;
+    let mut message_format = MessageFormat::Human;
+    let mut drafts = false;
+    let mut offline = false;
+    let mut dry_run = false;
+    let mut port = 8000;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let (flag, inline) = match arg.split_once('=') {
+            Some((flag, value)) if arg.starts_with("--") => (flag, Some(value)),
+            _ => (arg.as_str(), None),
+        };
+        match flag {
+            "-h" | "--help" => {
+                print!("{USAGE}");
+                return Ok(ExitCode::SUCCESS);
+            }
+            "--root" => root = PathBuf::from(flag_value(flag, inline, &mut args)?),
+            "--output" => output = Some(PathBuf::from(flag_value(flag, inline, &mut args)?)),
+            "--config" => config = Some(PathBuf::from(flag_value(flag, inline, &mut args)?)),
+            "--message-format" => {
+                message_format = match flag_value(flag, inline, &mut args)?.as_str() {
+                    "human" => MessageFormat::Human,
+                    "json" => MessageFormat::Json,
+                    other => eyre::bail!("unknown message format {other}, expected human or json"),
                 }
             }
-
-            for log in db.logs.lock().unwrap().drain(..) {
-                eprintln!("{log}");
+            "--drafts" => drafts = true,
+            "--offline" => offline = true,
+            "--dry-run" => dry_run = true,
+            "--port" => {
+                port = flag_value(flag, inline, &mut args)?
+                    .parse::<u16>()
+                    .context("--port must be a number")?
             }
-            Ok(())
+            _ if flag.starts_with('-') => eyre::bail!("unknown flag {flag}, see --help"),
+            _ => positional.push(arg),
         }
-        "watch" => main_watch(),
-        _ => panic!("unknown mode. Options: [run|watch]"),
+    }
+
+    let root = root
+        .canonicalize()
+        .with_context(|| format!("could not find the root {}", root.display()))?;
+    let keep_output = output.is_some();
+    let default_output = if positional.first().is_some_and(|command| command == "check") {
+        std::env::temp_dir().join(format!("site-check-{}", std::process::id()))
+    } else {
+        root.join("output")
+    };
+    let options = BuildOptions {
+        output: output.unwrap_or(default_output),
+        config: config.unwrap_or_else(|| root.join(site::SITE_PATH)),
+        root,
+        drafts,
+        today: jiff::Zoned::now().date(),
+    };
+
+    let mut positional = positional.into_iter();
+    let Some(command) = positional.next() else {
+        eprint!("{USAGE}");
+        return Ok(ExitCode::FAILURE);
+    };
+    // only `new` takes more than one argument, the words of the title
+    let argument = positional.next();
+    let rest: Vec<String> = positional.collect();
+    if !rest.is_empty() && command != "new" {
+        eyre::bail!("unexpected argument {}, see --help", rest[0]);
+    }
+    match (command.as_str(), argument) {
+        ("build", None) => {
+            create_output_dirs(&options.output)?;
+            main_build(options, message_format, dry_run)
+        }
+        ("watch", None) => {
+            create_output_dirs(&options.output)?;
+            main_watch(options, message_format, dry_run, None)
+        }
+        ("serve", None) => {
+            create_output_dirs(&options.output)?;
+            let address = format!("127.0.0.1:{port}");
+            let server = serve::Server::start(&options.output, &address)?;
+            eprintln!("serving the site on http://{address}");
+            main_watch(options, message_format, dry_run, Some(&server))
+        }
+        ("check", None) => main_check(options, message_format, dry_run, keep_output),
+        ("check-links", None) => main_check_links(options, message_format, offline),
+        ("new", Some(first)) => {
+            let title = [first]
+                .into_iter()
+                .chain(rest)
+                .collect::<Vec<_>>()
+                .join(" ");
+            main_new(&options, &title)
+        }
+        ("render", Some(path)) => main_render(options, Path::new(&path)),
+        ("new" | "render", None) => eyre::bail!("{command} needs an argument, see --help"),
+        ("build" | "watch" | "serve" | "check" | "check-links", Some(argument)) => {
+            eyre::bail!("unexpected argument {argument}, see --help")
+        }
+        (command, _) => eyre::bail!("unknown command {command}, see --help"),
     }
 }
 
-fn public_input_files() -> Result<Vec<PathBuf>, Error> {
-    let mut results = Vec::new();
-    for entry in fs::read_dir("./public").context("could not read public directory")? {
-        let entry = entry?;
-        results.push(entry.path().to_owned());
-    }
-    // TODO search code
-    // for entry in
-    //     fs::read_dir("./search/dist/assets").context("could not read search dist directory")?
-    // {
-    //     let entry = entry?;
-    //     results.push(entry.path().to_owned());
-    // }
-
-    Ok(results)
-}
-
-fn public_dir() -> Result<HashMap<String, String>, Error> {
-    let mut map = HashMap::new();
-    for entry in public_input_files().context("could not list public files")? {
-        let file_name = entry.file_name().unwrap().to_str().unwrap().to_owned();
-        let mut file = fs::File::open(&entry).context("could not read public file")?;
-        let mut hasher = Sha1::new();
-        io::copy(&mut file, &mut hasher).context("copy failed")?;
-        let hash = hasher.finalize();
-
-        let base64_hash: String = Base64UrlUnpadded::encode_string(&hash)
-            .chars()
-            .take(7)
-            .collect();
+fn hashed_name(data: &[u8], file_name: &str) -> String {
+    let hash = Sha1::digest(data);
+    let base64_hash: String = Base64UrlUnpadded::encode_string(&hash)
+        .chars()
+        .take(7)
+        .collect();
+    format!("{}_{}", base64_hash, file_name)
+}
+
+fn main() -> Result<ExitCode, Error> {
+    main_salsa()
+}
 
-        let hashed_name = format!("{}_{}", base64_hash, file_name);
-        let mut output_path = PathBuf::from("./output/public");
-        output_path.push(&hashed_name);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        fs::copy(&entry, &output_path).context("could not copy hashed file to output/public")?;
-        map.insert(file_name.to_owned(), hashed_name);
+    /// One iteration of `main_watch`: writes the output and removes the
+    /// files that became stale.
+    fn rebuild(
+        db: &db::BlogDatabase,
+        root: Dir,
+        previous: Option<HashSet<PathBuf>>,
+    ) -> HashSet<PathBuf> {
+        output_dir(db, root);
+        let manifest = output_manifest(db, root);
+        clean_output(db.options(), &manifest, previous.as_ref(), false);
+        manifest
     }
-    Ok(map)
-}
 
-fn main() -> Result<(), Error> {
-    fs::create_dir_all("./output/articles").context("could not create output dir")?;
-    fs::create_dir_all("./output/tags").context("could not create output dir")?;
-    fs::create_dir_all("./output/public").context("could not create output dir")?;
-    main_salsa()?;
-    return Ok(());
+    fn written_style(manifest: &HashSet<PathBuf>) -> PathBuf {
+        manifest
+            .iter()
+            .find(|path| path.to_string_lossy().ends_with("_style.css"))
+            .unwrap()
+            .clone()
+    }
 
-    // TODO
-    // - search
-    // - shared navbar code
+    #[test]
+    fn watch_writes_reverted_asset_again() {
+        let root = std::env::temp_dir().join(format!("site-watch-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("articles")).unwrap();
+        fs::create_dir_all(root.join("public")).unwrap();
+        fs::write(
+            root.join(site::SITE_PATH),
+            "title: Test\nbase_url: https://example.com\nauthor: Test\n",
+        )
+        .unwrap();
+        let root = root.canonicalize().unwrap();
+        let style = root.join("public/style.css");
+        fs::write(&style, "body { color: red; }").unwrap();
+        let options = BuildOptions {
+            output: root.join("output"),
+            config: root.join(site::SITE_PATH),
+            root: root.clone(),
+            drafts: false,
+            today: jiff::civil::date(2026, 1, 1),
+        };
+        create_output_dirs(&options.output).unwrap();
+        let mut db = db::BlogDatabase::new(options);
+        let root_dir = db.dir(root.clone()).unwrap();
+
+        let manifest = rebuild(&db, root_dir, None);
+        let original = written_style(&manifest);
+
+        fs::write(&style, "body { color: blue; }").unwrap();
+        db.reload_path(&style).unwrap();
+        let manifest = rebuild(&db, root_dir, Some(manifest));
+        assert_ne!(written_style(&manifest), original);
+        assert!(!original.exists());
 
-    // let search_html = templates::search::render_seach();
-    // let search_html =
-    //     rewrite_html(".", &asset_map, &search_html).context("coult not rewrite search")?;
-    // fs::write("output/search.html", search_html).context("could not write search.html")?;
+        fs::write(&style, "body { color: red; }").unwrap();
+        db.reload_path(&style).unwrap();
+        let manifest = rebuild(&db, root_dir, Some(manifest));
+        assert_eq!(written_style(&manifest), original);
+        assert!(original.exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
 }
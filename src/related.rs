@@ -0,0 +1,53 @@
+//! Related posts, scored by shared tags and TF-IDF similarity of the plaintext.
+use std::collections::HashMap;
+
+/// How many related posts are shown below an article.
+pub const RELATED_POSTS: usize = 3;
+
+/// Added to the score for every tag two articles share, the text similarity is
+/// between 0 and 1.
+pub const TAG_WEIGHT: f64 = 0.2;
+
+/// Counts the lowercase words in a text, skipping words shorter than 3
+/// characters as they are mostly stop words.
+pub fn term_counts(text: &str) -> HashMap<String, u32> {
+    let mut counts = HashMap::new();
+    let text = text.to_lowercase();
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        if word.chars().count() < 3 {
+            continue;
+        }
+        *counts.entry(word.to_owned()).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Weighs the term counts of a document by how rare the terms are in all
+/// `documents`, `document_frequencies` is the amount of documents with a term.
+pub fn tf_idf<'a>(
+    counts: &'a HashMap<String, u32>,
+    document_frequencies: &HashMap<String, u32>,
+    documents: usize,
+) -> HashMap<&'a str, f64> {
+    let total: u32 = counts.values().sum();
+    counts
+        .iter()
+        .map(|(term, count)| {
+            let frequency = document_frequencies.get(term).copied().unwrap_or(0);
+            // smoothed, so terms in every document still count a little
+            let idf = ((1 + documents) as f64 / (1 + frequency) as f64).ln() + 1.0;
+            let tf = *count as f64 / total.max(1) as f64;
+            (term.as_str(), tf * idf)
+        })
+        .collect()
+}
+
+pub fn cosine_similarity(left: &HashMap<&str, f64>, right: &HashMap<&str, f64>) -> f64 {
+    let dot: f64 = left
+        .iter()
+        .filter_map(|(term, weight)| right.get(term).map(|other| weight * other))
+        .sum();
+    let norm = |vector: &HashMap<&str, f64>| vector.values().map(|w| w * w).sum::<f64>().sqrt();
+    let norms = norm(left) * norm(right);
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
@@ -0,0 +1,113 @@
+//! Full-text search index for the static site.
+//!
+//! Each article gets a bloom filter (bit signature) over the trigrams of its
+//! plaintext. `public/search.js` splits the query into trigrams the same way and
+//! checks them against every signature, so searching needs no server.
+use std::collections::HashSet;
+
+use base64ct::{Base64, Encoding};
+use serde::Serialize;
+
+const FALSE_POSITIVE_RATE: f64 = 0.01;
+
+#[derive(Serialize)]
+pub struct SearchIndex {
+    pub articles: Vec<IndexEntry>,
+}
+
+#[derive(Serialize)]
+pub struct IndexEntry {
+    pub title: String,
+    pub url: String,
+    pub date: Option<String>,
+    /// Size of the signature in bits.
+    pub bits: u32,
+    pub hashes: u32,
+    /// Base64 encoded bitset, least significant bit first.
+    pub signature: String,
+}
+
+/// Splits text into lowercase words and returns their trigrams.
+///
+/// Words shorter than 3 characters are kept as is, so short queries still work.
+/// Must stay in sync with `tokens` in `public/search.js`.
+pub fn trigrams(text: &str) -> HashSet<String> {
+    let mut result = HashSet::new();
+    let text = text.to_lowercase();
+    for word in text.split(|c: char| !c.is_alphanumeric()) {
+        let chars: Vec<char> = word.chars().collect();
+        if chars.is_empty() {
+            continue;
+        }
+        if chars.len() < 3 {
+            result.insert(word.to_owned());
+            continue;
+        }
+        for window in chars.windows(3) {
+            result.insert(window.iter().collect());
+        }
+    }
+    result
+}
+
+fn fnv1a(bytes: &[u8], seed: u32) -> u32 {
+    let mut hash = 0x811c9dc5u32 ^ seed;
+    for byte in bytes {
+        hash ^= *byte as u32;
+        hash = hash.wrapping_mul(0x01000193);
+    }
+    hash
+}
+
+struct BloomFilter {
+    bits: Vec<u8>,
+    num_bits: u32,
+    num_hashes: u32,
+}
+
+impl BloomFilter {
+    /// Sizes the filter for `items` entries at the given false positive rate.
+    fn with_capacity(items: usize, false_positive_rate: f64) -> Self {
+        let n = items.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-n * false_positive_rate.ln() / (ln2 * ln2)).ceil() as u32;
+        // round up to whole bytes
+        let num_bits = num_bits.div_ceil(8) * 8;
+        let num_hashes = ((num_bits as f64 / n) * ln2).round().max(1.0) as u32;
+        BloomFilter {
+            bits: vec![0; (num_bits / 8) as usize],
+            num_bits,
+            num_hashes,
+        }
+    }
+
+    /// Double hashing, `h1 + i * h2`, see `positions` in `public/search.js`.
+    fn insert(&mut self, item: &str) {
+        let h1 = fnv1a(item.as_bytes(), 0);
+        let h2 = fnv1a(item.as_bytes(), 0x5bd1e995);
+        for i in 0..self.num_hashes {
+            let position = h1.wrapping_add(i.wrapping_mul(h2)) % self.num_bits;
+            self.bits[(position / 8) as usize] |= 1 << (position % 8);
+        }
+    }
+}
+
+pub fn index_entry(title: &str, url: &str, date: Option<String>, text: &str) -> IndexEntry {
+    // The title is searchable too
+    let mut grams = trigrams(text);
+    grams.extend(trigrams(title));
+
+    let mut filter = BloomFilter::with_capacity(grams.len(), FALSE_POSITIVE_RATE);
+    for gram in &grams {
+        filter.insert(gram);
+    }
+
+    IndexEntry {
+        title: title.to_owned(),
+        url: url.to_owned(),
+        date,
+        bits: filter.num_bits,
+        hashes: filter.num_hashes,
+        signature: Base64::encode_string(&filter.bits),
+    }
+}
@@ -0,0 +1,204 @@
+//! A development server for the output directory, used by the `serve` mode.
+//!
+//! Every html page gets a small live reload client, which listens for
+//! server-sent events from `/__livereload`. `Server::send` is called after
+//! every compile. The client also shows the diagnostics of the last compile in
+//! an overlay.
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use eyre::{Context, Error};
+
+const CLIENT_JS: &str = include_str!("livereload.js");
+const CLIENT_SCRIPT: &str = r#"<script src="/__livereload.js"></script>"#;
+
+#[derive(Clone)]
+pub struct Server {
+    root: PathBuf,
+    /// Open event streams of the pages being previewed.
+    clients: Arc<Mutex<Vec<TcpStream>>>,
+    /// Json array of the diagnostics of the last compile, sent to pages when
+    /// they connect.
+    diagnostics: Arc<Mutex<String>>,
+}
+
+impl Server {
+    /// Serves `root` on `address` from a background thread.
+    pub fn start(root: &Path, address: &str) -> Result<Server, Error> {
+        let listener =
+            TcpListener::bind(address).with_context(|| format!("could not listen on {address}"))?;
+        let server = Server {
+            root: root.to_path_buf(),
+            clients: Default::default(),
+            diagnostics: Arc::new(Mutex::new("[]".to_owned())),
+        };
+        let accept_server = server.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let server = accept_server.clone();
+                thread::spawn(move || {
+                    if let Err(err) = server.handle(stream) {
+                        eprintln!("dev server: {err:#}");
+                    }
+                });
+            }
+        });
+        Ok(server)
+    }
+
+    /// Sends an event to every open page, dropping the ones that are closed.
+    pub fn send(&self, event: &str, data: &str) {
+        let message = event_message(event, data);
+        let mut clients = self.clients.lock().unwrap();
+        clients.retain_mut(|client| client.write_all(message.as_bytes()).is_ok());
+    }
+
+    /// Shows the diagnostics in the overlay of every open page, an empty json
+    /// array clears it.
+    pub fn set_diagnostics(&self, diagnostics_json: &str) {
+        *self.diagnostics.lock().unwrap() = diagnostics_json.to_owned();
+        self.send("diagnostics", diagnostics_json);
+    }
+
+    fn handle(&self, stream: TcpStream) -> Result<(), Error> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // the headers are not needed
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+        }
+
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return respond(stream, "400 Bad Request", "text/plain", b"bad request");
+        };
+        if method != "GET" && method != "HEAD" {
+            return respond(stream, "405 Method Not Allowed", "text/plain", b"");
+        }
+        let path = percent_decode(target.split(['?', '#']).next().unwrap_or("/"));
+
+        match path.as_str() {
+            "/__livereload" => {
+                let mut stream = stream;
+                stream.write_all(
+                    b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+                      Cache-Control: no-cache\r\nConnection: keep-alive\r\n\r\n",
+                )?;
+                let diagnostics = self.diagnostics.lock().unwrap().clone();
+                stream.write_all(event_message("diagnostics", &diagnostics).as_bytes())?;
+                self.clients.lock().unwrap().push(stream);
+                Ok(())
+            }
+            "/__livereload.js" => {
+                respond(stream, "200 OK", "text/javascript", CLIENT_JS.as_bytes())
+            }
+            path => self.serve_file(stream, path),
+        }
+    }
+
+    fn serve_file(&self, stream: TcpStream, path: &str) -> Result<(), Error> {
+        if path.split('/').any(|part| part == "..") {
+            return respond(stream, "403 Forbidden", "text/plain", b"forbidden");
+        }
+        let mut file_path = self.root.join(path.trim_start_matches('/'));
+        if file_path.is_dir() {
+            file_path.push("index.html");
+        }
+        let Ok(data) = fs::read(&file_path) else {
+            let body = format!("{path} not found");
+            return respond(stream, "404 Not Found", "text/plain", body.as_bytes());
+        };
+        let content_type = content_type(&file_path);
+        if content_type.starts_with("text/html") {
+            let html = inject_client(&String::from_utf8_lossy(&data));
+            return respond(stream, "200 OK", content_type, html.as_bytes());
+        }
+        respond(stream, "200 OK", content_type, &data)
+    }
+}
+
+fn event_message(event: &str, data: &str) -> String {
+    let mut message = format!("event: {event}\n");
+    // events without data are not dispatched
+    message.push_str("data: \n");
+    for line in data.lines() {
+        message.push_str(&format!("data: {line}\n"));
+    }
+    message.push('\n');
+    message
+}
+
+fn respond(
+    mut stream: TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> Result<(), Error> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\n\
+         Cache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    Ok(())
+}
+
+fn inject_client(html: &str) -> String {
+    match html.rfind("</body>") {
+        Some(index) => format!("{}{CLIENT_SCRIPT}{}", &html[..index], &html[index..]),
+        None => format!("{html}{CLIENT_SCRIPT}"),
+    }
+}
+
+fn percent_decode(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("css") => "text/css",
+        Some("js" | "mjs") => "text/javascript",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("webp") => "image/webp",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("ico") => "image/x-icon",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
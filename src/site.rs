@@ -0,0 +1,120 @@
+use crate::db::Db;
+use eyre::{Context, Error};
+use serde::Deserialize;
+
+/// The default site config, relative to the root.
+pub const SITE_PATH: &str = "site.yaml";
+
+#[derive(Deserialize, Clone, PartialEq, Eq)]
+pub struct SiteConfig {
+    pub title: String,
+    /// Absolute url of the site, without trailing slash.
+    pub base_url: String,
+    pub author: String,
+    #[serde(default)]
+    pub description: String,
+    /// The `lang` of the pages.
+    #[serde(default = "default_language")]
+    pub language: String,
+    /// Links in the navigation bar at the top of every page.
+    #[serde(default)]
+    pub nav: Vec<NavItem>,
+    #[serde(default)]
+    pub feeds: FeedSettings,
+    #[serde(default)]
+    pub features: Features,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Eq)]
+pub struct NavItem {
+    pub title: String,
+    pub url: String,
+}
+
+#[derive(Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct FeedSettings {
+    /// Only the newest entries are in a feed, all of them if not set.
+    pub limit: Option<usize>,
+    /// Also write a feed for every tag.
+    pub tags: bool,
+}
+
+impl Default for FeedSettings {
+    fn default() -> Self {
+        FeedSettings {
+            limit: None,
+            tags: true,
+        }
+    }
+}
+
+/// Parts of the site that can be turned off, all are on by default.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct Features {
+    pub search: bool,
+    /// The links page and its feed.
+    pub links: bool,
+    pub feeds: bool,
+    /// `sitemap.xml` and `robots.txt`.
+    pub sitemap: bool,
+    /// The "Referenced by" section below articles.
+    pub backlinks: bool,
+    pub related_posts: bool,
+}
+
+impl Default for Features {
+    fn default() -> Self {
+        Features {
+            search: true,
+            links: true,
+            feeds: true,
+            sitemap: true,
+            backlinks: true,
+            related_posts: true,
+        }
+    }
+}
+
+/// The part of the config used by every html page, see [`SiteConfig::layout`].
+#[derive(Default, Clone, PartialEq, Eq)]
+pub struct SiteLayout {
+    pub title: String,
+    pub author: String,
+    pub description: String,
+    pub language: String,
+    pub nav: Vec<NavItem>,
+    /// Link to the feeds in the head.
+    pub feeds: bool,
+    pub tag_feeds: bool,
+}
+
+impl SiteConfig {
+    pub fn layout(&self) -> SiteLayout {
+        SiteLayout {
+            title: self.title.clone(),
+            author: self.author.clone(),
+            description: self.description.clone(),
+            language: self.language.clone(),
+            nav: self.nav.clone(),
+            feeds: self.features.feeds,
+            tag_feeds: self.features.feeds && self.feeds.tags,
+        }
+    }
+}
+
+fn default_language() -> String {
+    "en".to_owned()
+}
+
+pub fn load_site(db: &dyn Db) -> Result<SiteConfig, Error> {
+    let file = db
+        .input(db.options().config.clone())
+        .context("could not read the site config")?;
+    let text = String::from_utf8_lossy(file.text(db)).to_string();
+    let mut site: SiteConfig =
+        serde_yaml::from_str(&text).context("could not parse the site config")?;
+    site.base_url = site.base_url.trim_end_matches('/').to_owned();
+    Ok(site)
+}
@@ -1,5 +1,7 @@
 pub mod article;
+pub mod feed;
 pub mod home;
 pub mod links;
 pub mod search;
+pub mod sitemap;
 pub mod tags;
@@ -1,10 +1,37 @@
 use askama::Template;
 
-use crate::article::Metadata;
+use crate::{article::Metadata, site::SiteLayout};
 
 #[derive(Template)]
 #[template(path = "article.html")]
 pub struct ArticleTemplate<'a> {
+    pub site: &'a SiteLayout,
     pub body: &'a str,
     pub metadata: &'a Metadata,
+    /// Shown above drafts and scheduled articles when previewing.
+    pub banner: Option<&'a str>,
+    /// Articles that link to this one, with their urls.
+    pub referenced_by: &'a [(Metadata, String)],
+    /// Related articles with their urls, best first.
+    pub related: &'a [(Metadata, String)],
+}
+
+pub fn render_article(
+    site: &SiteLayout,
+    body: &str,
+    metadata: &Metadata,
+    banner: Option<&str>,
+    referenced_by: &[(Metadata, String)],
+    related: &[(Metadata, String)],
+) -> String {
+    ArticleTemplate {
+        site,
+        body,
+        metadata,
+        banner,
+        referenced_by,
+        related,
+    }
+    .render()
+    .unwrap()
 }
@@ -0,0 +1,13 @@
+use askama::Template;
+
+use crate::feeds::Feed;
+
+#[derive(Template)]
+#[template(path = "feed.xml")]
+struct AtomTemplate<'a> {
+    feed: &'a Feed,
+}
+
+pub fn render_atom(feed: &Feed) -> String {
+    AtomTemplate { feed }.render().unwrap()
+}
@@ -3,10 +3,14 @@ use askama::Template;
 #[derive(Template)]
 #[template(path = "index.html")]
 struct HomeTemplate<'a> {
+    site: &'a crate::site::SiteLayout,
     articles: &'a [(crate::article::Metadata, String)],
 }
 
-pub fn render_home(articles: &[(crate::article::Metadata, String)]) -> String {
-    let output = HomeTemplate { articles }.render().unwrap();
+pub fn render_home(
+    site: &crate::site::SiteLayout,
+    articles: &[(crate::article::Metadata, String)],
+) -> String {
+    let output = HomeTemplate { site, articles }.render().unwrap();
     output
 }
@@ -3,9 +3,10 @@ use askama::Template;
 #[derive(Template)]
 #[template(path = "links.html")]
 struct LinksTemplate<'a> {
+    site: &'a crate::site::SiteLayout,
     links: &'a [crate::links::LinkEntry],
 }
 
-pub fn render_links(links: &[crate::links::LinkEntry]) -> String {
-    LinksTemplate { links }.render().unwrap()
+pub fn render_links(site: &crate::site::SiteLayout, links: &[crate::links::LinkEntry]) -> String {
+    LinksTemplate { site, links }.render().unwrap()
 }
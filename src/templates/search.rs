@@ -3,9 +3,11 @@ use askama::Template;
 #[derive(Template)]
 #[template(path = "search.html")]
 
-struct SearchTemplate {}
+struct SearchTemplate<'a> {
+    site: &'a crate::site::SiteLayout,
+}
 
-pub fn render_seach() -> String {
-    let output = SearchTemplate {}.render().unwrap();
+pub fn render_seach(site: &crate::site::SiteLayout) -> String {
+    let output = SearchTemplate { site }.render().unwrap();
     output
 }
@@ -0,0 +1,12 @@
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "sitemap.xml")]
+struct SitemapTemplate<'a> {
+    base_url: &'a str,
+    pages: &'a [(String, Option<jiff::civil::Date>)],
+}
+
+pub fn render_sitemap(base_url: &str, pages: &[(String, Option<jiff::civil::Date>)]) -> String {
+    SitemapTemplate { base_url, pages }.render().unwrap()
+}
@@ -3,11 +3,22 @@ use askama::Template;
 #[derive(Template)]
 #[template(path = "tag.html")]
 struct TagTemplate<'a> {
+    site: &'a crate::site::SiteLayout,
     articles: &'a [(crate::article::Metadata, String)],
     tag_name: &'a str,
 }
 
-pub fn render_tag_page(tag_name: &str, articles: &[(crate::article::Metadata, String)]) -> String {
-    let output = TagTemplate { tag_name, articles }.render().unwrap();
+pub fn render_tag_page(
+    site: &crate::site::SiteLayout,
+    tag_name: &str,
+    articles: &[(crate::article::Metadata, String)],
+) -> String {
+    let output = TagTemplate {
+        site,
+        tag_name,
+        articles,
+    }
+    .render()
+    .unwrap();
     output
 }
@@ -0,0 +1,45 @@
+//! Resolving `[[wikilinks]]` to article urls.
+//!
+//! A wikilink can name an article by its file stem, its title or one of the
+//! `aliases` in its front matter, ignoring case and repeated whitespace.
+use std::collections::HashMap;
+
+#[derive(Default, Clone, PartialEq, Eq)]
+pub struct WikiLinks {
+    targets: HashMap<String, Vec<String>>,
+}
+
+pub enum Resolved<'a> {
+    Found(&'a str),
+    Missing,
+    /// The name matches more than one article, with the urls of all of them.
+    Ambiguous(&'a [String]),
+}
+
+fn normalize(name: &str) -> String {
+    name.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+impl WikiLinks {
+    pub fn insert(&mut self, name: &str, url: &str) {
+        let name = normalize(name);
+        if name.is_empty() {
+            return;
+        }
+        let urls = self.targets.entry(name).or_default();
+        if !urls.iter().any(|existing| existing == url) {
+            urls.push(url.to_owned());
+        }
+    }
+
+    pub fn resolve(&self, name: &str) -> Resolved<'_> {
+        match self.targets.get(&normalize(name)).map(Vec::as_slice) {
+            None | Some([]) => Resolved::Missing,
+            Some([url]) => Resolved::Found(url),
+            Some(urls) => Resolved::Ambiguous(urls),
+        }
+    }
+}